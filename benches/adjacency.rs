@@ -0,0 +1,92 @@
+// Compares neighbor lookups through a precomputed Adjacency against the map itself
+// Run with `cargo bench --bench adjacency`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use csc411::{
+    map::{Map, Tile},
+    pathfinding::{astar, Graph},
+};
+use glam::IVec2;
+
+// Counts every allocation so lookups can be shown not to touch the heap
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Allocations made and time taken to run f
+fn measure(f: impl FnOnce()) -> (usize, f64) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, elapsed)
+}
+
+fn main() {
+    let map = Map::from_fn(64, 64, |pos| {
+        if pos.x % 8 == 4 && pos.y % 16 != 0 {
+            Tile::IMPASSABLE
+        } else {
+            Tile::CLEAN
+        }
+    });
+    let adjacency = map.build_adjacency();
+    let positions: Vec<IVec2> = map.get_tile_iterator().map(|(pos, _)| pos).collect();
+
+    let (allocations, elapsed) = measure(|| {
+        for pos in &positions {
+            black_box(map.get_neighbors(pos));
+        }
+    });
+    println!(
+        "get_neighbors: {} lookups, {} allocations, {:.3} ms",
+        positions.len(),
+        allocations,
+        elapsed
+    );
+
+    let (allocations, elapsed) = measure(|| {
+        for pos in &positions {
+            black_box(adjacency.neighbors(*pos).count());
+        }
+    });
+    println!(
+        "Adjacency: {} lookups, {} allocations, {:.3} ms",
+        positions.len(),
+        allocations,
+        elapsed
+    );
+    assert_eq!(allocations, 0, "adjacency lookups should not allocate");
+
+    let (start, goal) = (IVec2::new(0, 0), IVec2::new(63, 63));
+    let (_, map_elapsed) = measure(|| {
+        black_box(astar(&map, start, goal, None));
+    });
+    let (_, adjacency_elapsed) = measure(|| {
+        black_box(astar(&adjacency, start, goal, None));
+    });
+    println!(
+        "astar across the map: {:.3} ms on the map, {:.3} ms on the adjacency",
+        map_elapsed, adjacency_elapsed
+    );
+}
@@ -0,0 +1,102 @@
+// Compares allocations made by get_neighbors against the lazy neighbors_iter, and by a search over the map
+// Run with `cargo bench --bench neighbors`
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use csc411::{
+    map::{Map, Tile},
+    pathfinding::{astar, Graph},
+};
+use glam::IVec2;
+
+// Counts every allocation so lookups can be shown not to touch the heap
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Allocations made and time taken to run f
+fn measure(f: impl FnOnce()) -> (usize, f64) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed().as_secs_f64() * 1000.0;
+    (ALLOCATIONS.load(Ordering::Relaxed) - before, elapsed)
+}
+
+fn main() {
+    let mut map = Map::from_fn(64, 64, |pos| {
+        if pos.y % 8 == 4 && pos.x % 16 != 0 {
+            Tile::IMPASSABLE
+        } else {
+            Tile::CLEAN
+        }
+    });
+    map.add_portal(IVec2::new(0, 0), IVec2::new(63, 63), true);
+    let positions: Vec<IVec2> = map.get_tile_iterator().map(|(pos, _)| pos).collect();
+
+    let (allocations, elapsed) = measure(|| {
+        for pos in &positions {
+            black_box(map.get_neighbors(pos));
+        }
+    });
+    println!(
+        "get_neighbors: {} lookups, {} allocations, {:.3} ms",
+        positions.len(),
+        allocations,
+        elapsed
+    );
+
+    let (allocations, elapsed) = measure(|| {
+        for pos in &positions {
+            black_box(map.neighbors_iter(*pos).count());
+        }
+    });
+    println!(
+        "neighbors_iter: {} lookups, {} allocations, {:.3} ms",
+        positions.len(),
+        allocations,
+        elapsed
+    );
+    assert_eq!(allocations, 0, "neighbors_iter should not allocate");
+
+    // The Graph impl searches use, portals included
+    let (allocations, elapsed) = measure(|| {
+        for pos in &positions {
+            black_box(map.neighbors(*pos).count());
+        }
+    });
+    println!(
+        "Graph::neighbors: {} lookups, {} allocations, {:.3} ms",
+        positions.len(),
+        allocations,
+        elapsed
+    );
+    assert_eq!(allocations, 0, "Graph::neighbors on a map should not allocate");
+
+    let (allocations, elapsed) = measure(|| {
+        black_box(astar(&map, IVec2::new(1, 0), IVec2::new(62, 63), None));
+    });
+    println!(
+        "astar across the map: {} allocations, {:.3} ms",
+        allocations, elapsed
+    );
+}
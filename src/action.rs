@@ -1,5 +1,19 @@
 use glam::IVec2;
 
+use crate::map::Map;
+
+// Offsets of the eight L-shaped knight moves, for use with Map::neighbors_by_offsets
+pub const KNIGHT_OFFSETS: [IVec2; 8] = [
+    IVec2::new(1, -2),
+    IVec2::new(2, -1),
+    IVec2::new(2, 1),
+    IVec2::new(1, 2),
+    IVec2::new(-1, 2),
+    IVec2::new(-2, 1),
+    IVec2::new(-2, -1),
+    IVec2::new(-1, -2),
+];
+
 /**
  * Represents a possible movement direction for the agent.
  */
@@ -9,11 +23,80 @@ pub enum Direction {
     Down,
     Left,
     Right,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
 }
 
+const CARDINAL_DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+];
+
+const ALL_DIRECTIONS: [Direction; 8] = [
+    Direction::Up,
+    Direction::Down,
+    Direction::Left,
+    Direction::Right,
+    Direction::UpLeft,
+    Direction::UpRight,
+    Direction::DownLeft,
+    Direction::DownRight,
+];
+
 impl Direction {
+    // Cardinal directions only, used by grid-locked agents
     pub fn all() -> [Direction; 4] {
-        [Self::Up, Self::Down, Self::Left, Self::Right]
+        CARDINAL_DIRECTIONS
+    }
+
+    // Cardinal and diagonal directions
+    pub fn all8() -> [Direction; 8] {
+        ALL_DIRECTIONS
+    }
+
+    // Iterates the cardinal directions in the order of all()
+    pub fn iter() -> DirectionIter {
+        DirectionIter {
+            directions: CARDINAL_DIRECTIONS.iter(),
+        }
+    }
+
+    // Iterates the cardinal and diagonal directions in the order of all8()
+    pub fn iter8() -> DirectionIter {
+        DirectionIter {
+            directions: ALL_DIRECTIONS.iter(),
+        }
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        matches!(
+            self,
+            Self::UpLeft | Self::UpRight | Self::DownLeft | Self::DownRight
+        )
+    }
+
+    // Direction matching a unit offset, including diagonals
+    pub fn from_ivec2(offset: IVec2) -> Option<Direction> {
+        Self::all8()
+            .into_iter()
+            .find(|direction| direction.to_ivec2() == offset)
+    }
+
+    // Cardinal direction along the axis of largest displacement from one position toward another
+    // Ties between the axes prefer the horizontal direction, None if the positions are equal
+    pub fn toward(from: IVec2, to: IVec2) -> Option<Direction> {
+        let delta = to - from;
+        if delta == IVec2::ZERO {
+            None
+        } else if delta.x.abs() >= delta.y.abs() {
+            Some(if delta.x > 0 { Self::Right } else { Self::Left })
+        } else {
+            Some(if delta.y > 0 { Self::Down } else { Self::Up })
+        }
     }
 
     pub fn to_ivec2(&self) -> IVec2 {
@@ -22,10 +105,42 @@ impl Direction {
             Self::Down => IVec2::new(0, 1),
             Self::Left => IVec2::new(-1, 0),
             Self::Right => IVec2::new(1, 0),
+            Self::UpLeft => IVec2::new(-1, -1),
+            Self::UpRight => IVec2::new(1, -1),
+            Self::DownLeft => IVec2::new(-1, 1),
+            Self::DownRight => IVec2::new(1, 1),
         }
     }
 }
 
+/**
+ * Iterator over a fixed set of directions, returned by Direction::iter and Direction::iter8.
+ */
+#[derive(Clone, Debug)]
+pub struct DirectionIter {
+    directions: std::slice::Iter<'static, Direction>,
+}
+
+impl Iterator for DirectionIter {
+    type Item = Direction;
+
+    fn next(&mut self) -> Option<Direction> {
+        self.directions.next().copied()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.directions.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for DirectionIter {
+    fn next_back(&mut self) -> Option<Direction> {
+        self.directions.next_back().copied()
+    }
+}
+
+impl ExactSizeIterator for DirectionIter {}
+
 /**
  * An action that can be taken by the agent.
  */
@@ -33,4 +148,111 @@ impl Direction {
 pub enum Action {
     Move { direction: Direction },
     Wait,
-}
\ No newline at end of file
+    // Cleans a dirty tile the agent is standing on
+    Clean,
+    // Jumps through the portal the agent is standing on to its exit
+    Teleport,
+}
+
+// Applies a fixed plan for a single agent and returns its position after each action
+// Moves off the map, onto impassable tiles or against a one-way tile leave the agent in place
+pub fn simulate_actions(map: &Map, start: IVec2, actions: &[Action]) -> Vec<IVec2> {
+    let mut position = start;
+    actions
+        .iter()
+        .map(|action| {
+            if let Action::Move { direction } = action {
+                let next = position + direction.to_ivec2();
+                if map.can_leave(&position, *direction)
+                    && map.get_tile(next).is_some_and(|tile| tile.is_passable())
+                {
+                    position = next;
+                }
+            }
+            position
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_moves_leave_the_agent_in_place() {
+        let map = Map::load_from_reader("CWC\nCCC\n".as_bytes()).unwrap();
+        let step = |direction| Action::Move { direction };
+        let plan = [
+            step(Direction::Right),
+            step(Direction::Down),
+            Action::Wait,
+            step(Direction::Right),
+            step(Direction::Right),
+            step(Direction::Down),
+            step(Direction::Up),
+        ];
+        assert_eq!(
+            simulate_actions(&map, IVec2::new(0, 0), &plan),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(0, 1),
+                IVec2::new(0, 1),
+                IVec2::new(1, 1),
+                IVec2::new(2, 1),
+                IVec2::new(2, 1),
+                IVec2::new(2, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn toward_picks_the_dominant_axis() {
+        let origin = IVec2::new(2, 2);
+        assert_eq!(
+            Direction::toward(origin, IVec2::new(9, -1)),
+            Some(Direction::Right)
+        );
+        assert_eq!(
+            Direction::toward(origin, IVec2::new(4, -8)),
+            Some(Direction::Up)
+        );
+        assert_eq!(
+            Direction::toward(origin, IVec2::new(-5, 3)),
+            Some(Direction::Left)
+        );
+        assert_eq!(
+            Direction::toward(origin, IVec2::new(2, 3)),
+            Some(Direction::Down)
+        );
+        // Equal displacement on both axes goes horizontally
+        assert_eq!(
+            Direction::toward(origin, IVec2::new(5, -1)),
+            Some(Direction::Right)
+        );
+        assert_eq!(Direction::toward(origin, origin), None);
+    }
+
+    #[test]
+    fn direction_iterators_yield_unit_offsets() {
+        let offsets: Vec<IVec2> = Direction::iter()
+            .map(|direction| direction.to_ivec2())
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                IVec2::new(0, -1),
+                IVec2::new(0, 1),
+                IVec2::new(-1, 0),
+                IVec2::new(1, 0)
+            ]
+        );
+        assert_eq!(Direction::iter().len(), 4);
+        assert_eq!(Direction::iter().next_back(), Some(Direction::Right));
+
+        let diagonals: Vec<Direction> = Direction::iter8().filter(Direction::is_diagonal).collect();
+        assert_eq!(diagonals.len(), 4);
+        assert!(Direction::iter8()
+            .map(|direction| direction.to_ivec2())
+            .all(|offset| offset != IVec2::ZERO && offset.abs().max_element() == 1));
+    }
+}
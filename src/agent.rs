@@ -1,4 +1,180 @@
+use std::collections::HashMap;
+
+use glam::IVec2;
+
+use crate::{
+    action::{Action, Direction},
+    map::Map,
+};
+
+// Inventory item that lets an agent pass through doors
+pub const KEY_ITEM: &str = "key";
+
+/**
+ * Mutable state carried by an agent, stored as item counts by name.
+ * The environment queries it to gate interactions such as passing doors.
+ */
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct AgentState {
+    items: HashMap<String, u32>,
+    // Energy left for moving, None for unlimited
+    energy: Option<u32>,
+}
+
+impl AgentState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    pub fn has(&self, item: &str) -> bool {
+        self.get(item) > 0
+    }
+
+    pub fn add(&mut self, item: &str, count: u32) {
+        *self.items.entry(item.to_string()).or_insert(0) += count;
+    }
+
+    pub fn energy(&self) -> Option<u32> {
+        self.energy
+    }
+
+    pub fn set_energy(&mut self, energy: Option<u32>) {
+        self.energy = energy;
+    }
+
+    // Uses up energy, stopping at zero, agents with unlimited energy are unaffected
+    pub fn consume_energy(&mut self, amount: u32) {
+        if let Some(energy) = &mut self.energy {
+            *energy = energy.saturating_sub(amount);
+        }
+    }
+
+    // Whether the agent has run out of energy, never true for unlimited energy
+    pub fn is_exhausted(&self) -> bool {
+        self.energy == Some(0)
+    }
+
+    // Removes up to count of an item, returning how many were actually removed
+    pub fn take(&mut self, item: &str, count: u32) -> u32 {
+        let held = self.get(item);
+        let taken = held.min(count);
+        if held - taken == 0 {
+            self.items.remove(item);
+        } else {
+            self.items.insert(item.to_string(), held - taken);
+        }
+        taken
+    }
+}
+
+/**
+ * How an agent is drawn by renderers that go beyond its plain symbol.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AgentStyle {
+    pub symbol: char,
+    // RGB color, None to use the terminal's default
+    pub color: Option<[u8; 3]>,
+}
+
 pub trait Agent {
     // Get textual representation of the agent
     fn get_symbol(&self) -> String;
-}
\ No newline at end of file
+    // Get and set the agent's position on the map
+    fn position(&self) -> IVec2;
+    fn set_position(&mut self, position: IVec2);
+    // Get the agent's inventory and other carried state
+    // Agents without state keep the defaults, so they never pick up items, open doors or run out of energy
+    fn state(&self) -> Option<&AgentState> {
+        None
+    }
+    fn state_mut(&mut self) -> Option<&mut AgentState> {
+        None
+    }
+
+    // Picks the agent's next action given the map and its goal, for environments that let agents drive themselves
+    // Agents steered by their environment keep the default of waiting
+    fn choose_action(&mut self, _map: &Map, _goal: Option<IVec2>) -> Action {
+        Action::Wait
+    }
+
+    // Goal the agent has chosen for itself, which environments that let agents drive themselves use over their own
+    fn goal(&self) -> Option<IVec2> {
+        None
+    }
+
+    // Called by the environment when the agent reaches its goal, so it can choose a new goal or stop
+    fn on_goal_reached(&mut self, _map: &Map) {}
+
+    // Whether the agent has given up on reaching its goal, such as a planner finding no path, which fails the environment
+    fn is_stuck(&self) -> bool {
+        false
+    }
+
+    // Direction the agent is facing, for agents that track orientation
+    fn facing(&self) -> Option<Direction> {
+        None
+    }
+
+    // Called by the environment with the direction of each move that goes through
+    fn set_facing(&mut self, _direction: Direction) {}
+
+    // Glyph used when rendering the agent, pointing in its facing direction when it has one
+    fn get_glyph(&self) -> String {
+        match self.facing() {
+            Some(Direction::Up) => "^".to_string(),
+            Some(Direction::Down) => "v".to_string(),
+            Some(Direction::Left) => "<".to_string(),
+            Some(Direction::Right) => ">".to_string(),
+            _ => self.get_symbol(),
+        }
+    }
+
+    // Style used by the colored renderer, defaulting to the first character of the glyph without a color
+    // Drawing the glyph keeps the colored renderer showing the same facing as Environment::render
+    fn style(&self) -> AgentStyle {
+        AgentStyle {
+            symbol: self.get_glyph().chars().next().unwrap_or('?'),
+            color: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Compass {
+        facing: Option<Direction>,
+    }
+
+    impl Agent for Compass {
+        fn get_symbol(&self) -> String {
+            "R".to_string()
+        }
+
+        fn position(&self) -> IVec2 {
+            IVec2::ZERO
+        }
+
+        fn set_position(&mut self, _position: IVec2) {}
+
+        fn facing(&self) -> Option<Direction> {
+            self.facing
+        }
+    }
+
+    #[test]
+    fn glyph_points_where_the_agent_faces() {
+        let glyph = |facing| Compass { facing }.get_glyph();
+        assert_eq!(glyph(Some(Direction::Left)), "<");
+        assert_eq!(glyph(Some(Direction::Right)), ">");
+        assert_eq!(glyph(Some(Direction::Up)), "^");
+        assert_eq!(glyph(Some(Direction::Down)), "v");
+        assert_eq!(glyph(None), "R");
+    }
+}
@@ -0,0 +1,323 @@
+use glam::IVec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    action::{Action, Direction},
+    agent::{Agent, AgentState, AgentStyle},
+    environment::EnvironmentConfig,
+    map::{Map, Tile},
+    pathfinding::{manhattan_distance, AStar, Metric, Step},
+};
+
+/**
+ * Agent that plans its route to the goal with A*, replanning whenever it ends up somewhere it did not expect.
+ */
+pub struct AStarAgent {
+    symbol: String,
+    position: IVec2,
+    state: AgentState,
+    astar: AStar,
+    // State the current plan was made with, a plan made before picking up a key may avoid a door it can now open
+    planned_with: AgentState,
+    // Whether the last plan found no way to the goal
+    stuck: bool,
+    // Whether to clean dirty tiles it stands on before moving on
+    cleaning: bool,
+    // Goal it heads for in place of the environment's, None to follow the environment
+    goal: Option<IVec2>,
+    // Heuristic each plan is searched with
+    metric: Metric,
+    // RGB color the colored renderer draws it in, None for the terminal's default
+    color: Option<[u8; 3]>,
+    // Direction of the last move, None before the first
+    facing: Option<Direction>,
+}
+
+impl AStarAgent {
+    pub fn new(position: IVec2) -> Self {
+        Self::with_symbol(position, "A")
+    }
+
+    pub fn with_symbol(position: IVec2, symbol: &str) -> Self {
+        AStarAgent {
+            symbol: symbol.to_string(),
+            position,
+            state: AgentState::new(),
+            astar: AStar::new(position),
+            planned_with: AgentState::new(),
+            stuck: false,
+            cleaning: false,
+            goal: None,
+            metric: Metric::Manhattan,
+            color: None,
+            facing: None,
+        }
+    }
+
+    // Plans with the config's heuristic
+    pub fn from_config(position: IVec2, config: &EnvironmentConfig) -> Self {
+        Self::new(position).with_metric(config.heuristic)
+    }
+
+    pub fn with_metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self.astar = AStar::with_metric(self.position, metric);
+        self
+    }
+
+    pub fn set_goal(&mut self, goal: Option<IVec2>) {
+        self.goal = goal;
+    }
+
+    pub fn set_cleaning(&mut self, cleaning: bool) {
+        self.cleaning = cleaning;
+    }
+
+    pub fn with_cleaning(mut self, cleaning: bool) -> Self {
+        self.set_cleaning(cleaning);
+        self
+    }
+
+    pub fn set_color(&mut self, color: Option<[u8; 3]>) {
+        self.color = color;
+    }
+
+    pub fn with_color(mut self, color: Option<[u8; 3]>) -> Self {
+        self.set_color(color);
+        self
+    }
+}
+
+impl Agent for AStarAgent {
+    fn get_symbol(&self) -> String {
+        self.symbol.clone()
+    }
+
+    fn position(&self) -> IVec2 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: IVec2) {
+        self.position = position;
+    }
+
+    fn state(&self) -> Option<&AgentState> {
+        Some(&self.state)
+    }
+
+    fn state_mut(&mut self) -> Option<&mut AgentState> {
+        Some(&mut self.state)
+    }
+
+    fn facing(&self) -> Option<Direction> {
+        self.facing
+    }
+
+    fn set_facing(&mut self, direction: Direction) {
+        self.facing = Some(direction);
+    }
+
+    // Waits when there is no goal or no path to it, a cleaning agent cleans before planning its next move
+    fn choose_action(&mut self, map: &Map, goal: Option<IVec2>) -> Action {
+        if self.cleaning && map.get_tile(self.position) == Some(&Tile::DIRTY) {
+            return Action::Clean;
+        }
+        let Some(goal) = goal else {
+            return Action::Wait;
+        };
+        // A rejected or external move or a change in what the agent carries leaves the plan out of date
+        // The planner itself replans for a new goal
+        if self.astar.position() != self.position || self.planned_with != self.state {
+            self.astar = AStar::with_metric(self.position, self.metric);
+            self.planned_with = self.state.clone();
+        }
+        let step = self.astar.run_on(map, &map.for_agent(&self.state), &goal);
+        self.stuck = step == Step::Stuck;
+        match step {
+            Step::Move(direction) => Action::Move { direction },
+            Step::Teleport => Action::Teleport,
+            Step::Arrived | Step::Stuck => Action::Wait,
+        }
+    }
+
+    fn goal(&self) -> Option<IVec2> {
+        self.goal
+    }
+
+    fn is_stuck(&self) -> bool {
+        self.stuck
+    }
+
+    fn style(&self) -> AgentStyle {
+        AgentStyle {
+            symbol: self.get_glyph().chars().next().unwrap_or('?'),
+            color: self.color,
+        }
+    }
+}
+
+/**
+ * Agent that moves to a uniformly random walkable neighbor each turn, ignoring its goal.
+ */
+pub struct RandomAgent {
+    symbol: String,
+    position: IVec2,
+    state: AgentState,
+    rng: StdRng,
+    // Direction of the last move, None before the first
+    facing: Option<Direction>,
+}
+
+impl RandomAgent {
+    pub fn new(position: IVec2, seed: u64) -> Self {
+        RandomAgent {
+            symbol: "?".to_string(),
+            position,
+            state: AgentState::new(),
+            rng: StdRng::seed_from_u64(seed),
+            facing: None,
+        }
+    }
+
+    // Moves randomly with the config's seed
+    pub fn from_config(position: IVec2, config: &EnvironmentConfig) -> Self {
+        Self::new(position, config.seed)
+    }
+}
+
+impl Agent for RandomAgent {
+    fn get_symbol(&self) -> String {
+        self.symbol.clone()
+    }
+
+    fn position(&self) -> IVec2 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: IVec2) {
+        self.position = position;
+    }
+
+    fn state(&self) -> Option<&AgentState> {
+        Some(&self.state)
+    }
+
+    fn state_mut(&mut self) -> Option<&mut AgentState> {
+        Some(&mut self.state)
+    }
+
+    fn facing(&self) -> Option<Direction> {
+        self.facing
+    }
+
+    fn set_facing(&mut self, direction: Direction) {
+        self.facing = Some(direction);
+    }
+
+    // Waits when boxed in
+    fn choose_action(&mut self, map: &Map, _goal: Option<IVec2>) -> Action {
+        let options: Vec<Direction> = map
+            .moves_iter(self.position)
+            .filter(|(_, _, tile)| tile.is_passable())
+            .map(|(_, direction, _)| direction)
+            .collect();
+        if options.is_empty() {
+            return Action::Wait;
+        }
+        Action::Move {
+            direction: options[self.rng.random_range(0..options.len())],
+        }
+    }
+}
+
+/**
+ * Agent that always steps to the neighbor closest to its goal by manhattan distance.
+ * It does no planning, so it waits once no neighbor is closer than where it stands, such as behind a wall.
+ */
+pub struct GreedyAgent {
+    symbol: String,
+    position: IVec2,
+    state: AgentState,
+    // Direction of the last move, None before the first
+    facing: Option<Direction>,
+}
+
+impl GreedyAgent {
+    pub fn new(position: IVec2) -> Self {
+        GreedyAgent {
+            symbol: "G".to_string(),
+            position,
+            state: AgentState::new(),
+            facing: None,
+        }
+    }
+}
+
+impl Agent for GreedyAgent {
+    fn get_symbol(&self) -> String {
+        self.symbol.clone()
+    }
+
+    fn position(&self) -> IVec2 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: IVec2) {
+        self.position = position;
+    }
+
+    fn state(&self) -> Option<&AgentState> {
+        Some(&self.state)
+    }
+
+    fn state_mut(&mut self) -> Option<&mut AgentState> {
+        Some(&mut self.state)
+    }
+
+    fn facing(&self) -> Option<Direction> {
+        self.facing
+    }
+
+    fn set_facing(&mut self, direction: Direction) {
+        self.facing = Some(direction);
+    }
+
+    fn choose_action(&mut self, map: &Map, goal: Option<IVec2>) -> Action {
+        let Some(goal) = goal else {
+            return Action::Wait;
+        };
+        let current_distance = manhattan_distance(&self.position, &goal);
+        match map.neighbors_sorted_toward(&self.position, goal).first() {
+            Some((next, direction, _tile))
+                if manhattan_distance(next, &goal) < current_distance =>
+            {
+                Action::Move {
+                    direction: *direction,
+                }
+            }
+            _ => Action::Wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_agent_from_config_uses_its_seed() {
+        let map = Map::from_fn(5, 5, |_| Tile::CLEAN);
+        let config = EnvironmentConfig {
+            seed: 11,
+            ..Default::default()
+        };
+        let mut seeded = RandomAgent::new(IVec2::new(2, 2), 11);
+        let mut configured = RandomAgent::from_config(IVec2::new(2, 2), &config);
+        for _ in 0..10 {
+            assert_eq!(
+                seeded.choose_action(&map, None),
+                configured.choose_action(&map, None)
+            );
+        }
+    }
+}
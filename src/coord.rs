@@ -0,0 +1,34 @@
+use glam::IVec2;
+
+// Helpers for converting between tile and pixel coordinates, since both are represented by IVec2
+
+// Top-left pixel of the tile at the given tile position
+pub fn tile_to_pixel(pos: IVec2, tile_size: i32) -> IVec2 {
+    pos * tile_size
+}
+
+// Tile containing the given pixel
+// Rounds toward negative infinity, so pixels left of or above the origin map to negative tiles
+pub fn pixel_to_tile(px: IVec2, tile_size: i32) -> IVec2 {
+    IVec2::new(px.x.div_euclid(tile_size), px.y.div_euclid(tile_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_pixels_round_trip() {
+        let pos = IVec2::new(3, 5);
+        assert_eq!(tile_to_pixel(pos, 16), IVec2::new(48, 80));
+        assert_eq!(pixel_to_tile(tile_to_pixel(pos, 16), 16), pos);
+    }
+
+    #[test]
+    fn pixels_round_down_to_their_tile() {
+        assert_eq!(pixel_to_tile(IVec2::new(15, 16), 16), IVec2::new(0, 1));
+        assert_eq!(pixel_to_tile(IVec2::new(31, 47), 16), IVec2::new(1, 2));
+        assert_eq!(pixel_to_tile(IVec2::new(-1, -16), 16), IVec2::new(-1, -1));
+        assert_eq!(pixel_to_tile(IVec2::new(-17, 0), 16), IVec2::new(-2, 0));
+    }
+}
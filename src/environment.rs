@@ -1,34 +1,408 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use glam::IVec2;
+use serde::{Deserialize, Serialize};
 
-use crate::{agent::Agent, map::Map};
+use crate::{
+    action::Action,
+    agent::Agent,
+    map::{Map, Tile},
+    pathfinding::Metric,
+    render::{draw_with_options, RenderOptions},
+};
 
 // Simple state enum for the environment
 // Run indicates that the environment ran the last turn
 // End indicates that the environment has reached a finishing state
+// Failed indicates that the environment stopped without reaching its goal
+// Paused indicates that running is suspended until resumed
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum EnvironmentState {
     START,
     RUN,
     END,
+    FAILED,
+    PAUSED,
+}
+
+impl EnvironmentState {
+    // Whether the environment has stopped for good, after which run() does nothing
+    pub fn is_finished(&self) -> bool {
+        matches!(self, EnvironmentState::END | EnvironmentState::FAILED)
+    }
+}
+
+/**
+ * What the environment does when an agent tries to move onto an impassable tile or off the map.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CollisionPolicy {
+    // The move is rejected and the agent stays in place
+    #[default]
+    Block,
+    // The environment transitions to FAILED
+    Error,
+}
+
+/**
+ * Condition under which an environment ends, checked after every step.
+ * Conditions compose, so ending on reaching the goal or after 10 turns is AnyOf(vec![ReachedGoal, MaxTurns(10)]).
+ */
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TerminationCondition {
+    // Met when every condition is met, including when there are none
+    AllOf(Vec<TerminationCondition>),
+    // Met when any condition is met, never when there are none
+    AnyOf(Vec<TerminationCondition>),
+    // Every agent that has a goal is standing on it
+    ReachedGoal,
+    NoDirtyTiles,
+    // The turn counter has reached the given turn
+    MaxTurns(u32),
+}
+
+impl TerminationCondition {
+    pub fn is_met(&self, env: &impl Environment) -> bool {
+        match self {
+            TerminationCondition::AllOf(conditions) => {
+                conditions.iter().all(|condition| condition.is_met(env))
+            }
+            TerminationCondition::AnyOf(conditions) => {
+                conditions.iter().any(|condition| condition.is_met(env))
+            }
+            TerminationCondition::ReachedGoal => env.get_agents().iter().all(|agent| {
+                env.get_goal(**agent)
+                    .is_none_or(|goal| goal == agent.position())
+            }),
+            TerminationCondition::NoDirtyTiles => {
+                env.get_map().get_all_of_type(Tile::DIRTY).is_empty()
+            }
+            TerminationCondition::MaxTurns(turns) => env.get_state().1 >= *turns,
+        }
+    }
+}
+
+/**
+ * Scenario parameters shared by all environments, passed in at construction.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EnvironmentConfig {
+    // Turn limit after which the environment fails, None for no limit
+    pub max_turns: Option<u32>,
+    pub collision_policy: CollisionPolicy,
+    // Seed for any randomness in the environment
+    pub seed: u64,
+    // Condition that ends the environment, None to end when the environment's own goal is reached
+    // Reaching max_turns still fails the environment if this condition is not met first
+    pub termination: Option<TerminationCondition>,
+    // Energy agents with state start with, None to keep the agent's own
+    pub energy: Option<u32>,
+    // Heuristic that planning agents built from the config search with
+    pub heuristic: Metric,
+}
+
+/**
+ * Structured record of something that happened during a step, for UIs and scorers.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Event {
+    AgentMoved {
+        symbol: String,
+        from: IVec2,
+        to: IVec2,
+    },
+    TileChanged {
+        pos: IVec2,
+        old: Tile,
+        new: Tile,
+    },
+    GoalReached {
+        symbol: String,
+    },
+}
+
+/**
+ * Outcome of a single call to run().
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct StepResult {
+    pub state: EnvironmentState,
+    pub turn: u32,
+    // Events emitted during this step, moved out of the environment so drain_events does not return them again
+    pub events: Vec<Event>,
 }
 
 /**
  * Environment represented as a trait, exposing functions needed to interact with interfaces such as GUIs or other systems.
  */
 pub trait Environment {
-    // Runs a step of the environment and updates its state accordingly
-    fn run(&mut self);
+    // Runs a step of the environment and updates its state accordingly, returning the state after the step
+    // While paused or once ended or failed this should do nothing, including not advancing the turn counter
+    fn run(&mut self) -> StepResult;
 
     // Get the map state from the environment
     fn get_map(&self) -> &Map;
     // Get agents in the environment
     fn get_agents(&self) -> Vec<Box<&impl Agent>>;
+    // Get mutable access to agents in the environment, so generic runners can drive them
+    fn agents_mut(&mut self) -> Vec<&mut dyn Agent>;
     // Gets the goal for a certain agent
     fn get_goal(&self, agent: &impl Agent) -> Option<IVec2>;
-    // Gets the environemnt state (START, RUN, END) along with a turn counter
+    // Gets the environemnt state (START, RUN, END, FAILED, PAUSED) along with a turn counter
     fn get_state(&self) -> (EnvironmentState, u32);
     // Get other information about the environment
     fn get_environment_info(&self) -> HashMap<String, String>;
+    // Gets the configuration the environment was constructed with
+    fn config(&self) -> &EnvironmentConfig;
+    // Flag that run() checks before doing any work, get_state() reports PAUSED while it is set
+    fn paused_mut(&mut self) -> &mut bool;
+    // Returns the events accumulated since the last call that no StepResult has carried, clearing them
+    fn drain_events(&mut self) -> Vec<Event>;
+
+    // Action the environment's acting agent took on the last step, None if it did not act or it is not tracked
+    fn last_action(&self) -> Option<Action> {
+        None
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        *self.paused_mut() = paused;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.get_state().0 == EnvironmentState::PAUSED
+    }
+
+    fn pause(&mut self) {
+        self.set_paused(true);
+    }
+
+    fn resume(&mut self) {
+        self.set_paused(false);
+    }
+
+    // Draws the map with every agent's glyph over its position, earlier agents are drawn over later ones
+    fn render(&self) -> String {
+        self.render_with(&RenderOptions::default())
+    }
+
+    // Draws the map as configured by the options, with agents drawn over it as in render()
+    fn render_with(&self, opts: &RenderOptions) -> String {
+        let overlays = self
+            .get_agents()
+            .iter()
+            .map(|agent| (agent.position(), agent.get_glyph()))
+            .collect();
+        draw_with_options(self.get_map(), opts, overlays)
+    }
+
+    // Runs up to n steps, stopping early once the environment has ended or failed
+    fn step_n(&mut self, n: u32) {
+        for _ in 0..n {
+            if self.get_state().0.is_finished() {
+                return;
+            }
+            self.run();
+        }
+    }
+}
+
+// Steps the environment until the duration of wall-clock time elapses or it ends or fails
+// Returns the final state and turn counter
+pub fn run_for(env: &mut impl Environment, duration: Duration) -> (EnvironmentState, u32) {
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        if env.get_state().0.is_finished() {
+            break;
+        }
+        env.run();
+    }
+    env.get_state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::Direction,
+        agents::{AStarAgent, RandomAgent},
+        simulation::{GenericEnvironment, SimulationEnvironment},
+    };
+
+    #[test]
+    fn render_draws_agents_over_the_map() {
+        let map = Map::load_from_reader("CCC\nCWC\nCCT\n".as_bytes()).unwrap();
+        let env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(1, 0)), IVec2::new(2, 2));
+        assert_eq!(env.render(), "CAC\nCWC\nCCT\n");
+        assert_eq!(env.to_string(), env.render());
+    }
+
+    #[test]
+    fn paused_run_does_not_advance() {
+        let map = Map::load_from_reader("CCCCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(4, 0));
+        env.run();
+        env.pause();
+        assert!(env.is_paused());
+        for _ in 0..3 {
+            let result = env.run();
+            assert_eq!((result.state, result.turn), (EnvironmentState::PAUSED, 1));
+            assert!(result.events.is_empty());
+        }
+        assert_eq!(env.agent().position(), IVec2::new(1, 0));
+
+        env.resume();
+        assert!(!env.is_paused());
+        assert_eq!(env.run().turn, 2);
+        assert_eq!(env.agent().position(), IVec2::new(2, 0));
+    }
+
+    #[test]
+    fn run_returns_each_event_once() {
+        let map = Map::load_from_reader("CCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(2, 0));
+
+        let result = env.run();
+        assert_eq!((result.state, result.turn), (EnvironmentState::RUN, 1));
+        assert_eq!(
+            result.events,
+            vec![Event::AgentMoved {
+                symbol: "A".to_string(),
+                from: IVec2::new(0, 0),
+                to: IVec2::new(1, 0),
+            }]
+        );
+        assert!(env.drain_events().is_empty());
+
+        let result = env.run();
+        assert_eq!((result.state, result.turn), (EnvironmentState::END, 2));
+        assert_eq!(result.events.len(), 2);
+        assert_eq!(
+            result.events[1],
+            Event::GoalReached {
+                symbol: "A".to_string()
+            }
+        );
+        assert!(env.drain_events().is_empty());
+    }
+
+    #[test]
+    fn step_n_advances_exactly_n_turns() {
+        let map = Map::load_from_reader("CCCCCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(5, 0));
+        env.step_n(3);
+        assert_eq!(env.get_state(), (EnvironmentState::RUN, 3));
+        assert_eq!(env.agent().position(), IVec2::new(3, 0));
+
+        env.step_n(10);
+        let (state, turn) = env.get_state();
+        assert_eq!(state, EnvironmentState::END);
+        assert_eq!(turn, 5);
+    }
+
+    #[test]
+    fn run_for_stops_when_time_is_up() {
+        // The target is walled off, so the wandering agent never finishes on its own
+        let map = Map::load_from_reader("CCC\nWWW\nCCT\n".as_bytes()).unwrap();
+        let mut env =
+            GenericEnvironment::new(map, RandomAgent::new(IVec2::new(0, 0), 3), IVec2::new(2, 2));
+        let started = Instant::now();
+        let (state, turn) = run_for(&mut env, Duration::from_millis(20));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(state, EnvironmentState::RUN);
+        assert!(turn > 0);
+
+        let map = Map::load_from_reader("CCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(2, 0));
+        assert_eq!(
+            run_for(&mut env, Duration::from_secs(60)),
+            (EnvironmentState::END, 2)
+        );
+    }
+
+    #[test]
+    fn reaching_the_goal_or_the_turn_limit_ends_the_environment() {
+        let config = EnvironmentConfig {
+            termination: Some(TerminationCondition::AnyOf(vec![
+                TerminationCondition::ReachedGoal,
+                TerminationCondition::MaxTurns(10),
+            ])),
+            ..Default::default()
+        };
+        let map = Map::load_from_reader("CCCCCCCCCCCCCCCCCCCT\n".as_bytes()).unwrap();
+
+        let mut near = GenericEnvironment::with_config(
+            map.clone(),
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(3, 0),
+            config.clone(),
+        );
+        near.step_n(1000);
+        assert_eq!(near.get_state(), (EnvironmentState::END, 3));
+        assert_eq!(near.agent().position(), IVec2::new(3, 0));
+
+        let mut far = GenericEnvironment::with_config(
+            map,
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(19, 0),
+            config,
+        );
+        far.step_n(1000);
+        assert_eq!(far.get_state(), (EnvironmentState::END, 10));
+        assert_eq!(far.agent().position(), IVec2::new(10, 0));
+    }
+
+    #[test]
+    fn running_after_the_end_does_not_advance_the_turn() {
+        let map = Map::load_from_reader("CCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(2, 0));
+        env.run();
+        assert_eq!(env.run().state, EnvironmentState::END);
+        for _ in 0..3 {
+            let result = env.run();
+            assert_eq!((result.state, result.turn), (EnvironmentState::END, 2));
+            assert!(result.events.is_empty());
+        }
+        assert_eq!(env.agent().position(), IVec2::new(2, 0));
+
+        let config = EnvironmentConfig {
+            max_turns: Some(1),
+            ..Default::default()
+        };
+        let map = Map::load_from_reader("CCCCT\n".as_bytes()).unwrap();
+        let mut env = GenericEnvironment::with_config(
+            map,
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(4, 0),
+            config,
+        );
+        env.step_n(5);
+        assert_eq!(env.get_state(), (EnvironmentState::FAILED, 1));
+        assert!(env.get_state().0.is_finished());
+    }
+
+    #[test]
+    fn moving_agent_renders_facing_its_last_move() {
+        let map = Map::load_from_reader("CCCT\nCCCC\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(3, 0));
+        assert_eq!(env.render(), "ACCT\nCCCC\n");
+        env.run();
+        assert_eq!(env.agent().facing(), Some(Direction::Right));
+        assert_eq!(env.render(), "C>CT\nCCCC\n");
+
+        let map = Map::load_from_reader("CT\nCC\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(1, 1)), IVec2::new(1, 0));
+        env.run();
+        assert_eq!(env.render(), "C^\nCC\n");
+    }
 }
@@ -1,4 +1,14 @@
 pub mod map;
 pub mod environment;
 pub mod action;
-pub mod agent;
\ No newline at end of file
+pub mod agent;
+pub mod pathfinding;
+pub mod simulation;
+pub mod policy;
+pub mod coord;
+pub mod prelude;
+pub mod pursuit;
+pub mod scenario;
+pub mod render;
+pub mod agents;
+pub mod replay;
@@ -1,85 +1,681 @@
 use std::{
-    collections::HashMap,
+    collections::{btree_map, BTreeMap, HashMap, HashSet, VecDeque},
     fmt::Display,
     fs::File,
-    io::{BufRead, BufReader, ErrorKind},
+    io::{BufRead, BufReader},
 };
 
 use glam::IVec2;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
-use crate::action::Direction;
+use crate::{
+    action::{Action, Direction},
+    agent::{AgentState, KEY_ITEM},
+    pathfinding::{
+        astar, manhattan_distance, Adjacency, AgentStateView, Blocked, ClearanceView,
+        CostModelView, DynamicObstacles, Graph, OffsetView, SearchOutcome,
+    },
+    render::{draw_with_options, RenderOptions},
+};
 
 /**
  * Basic tile implementation.
  * This may be refactored into a trait if each tile requires complex behavior in the future.
  */
-#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
 pub enum Tile {
     #[default]
     CLEAN,
     DIRTY,
     IMPASSABLE,
     TARGET,
+    KEY,
+    DOOR,
+}
+
+impl Tile {
+    // Whether an agent is able to stand on this tile
+    pub fn is_passable(&self) -> bool {
+        !matches!(self, Tile::IMPASSABLE | Tile::DOOR)
+    }
+
+    // Cost of moving onto this tile, None if it cannot be entered
+    pub fn movement_cost(&self) -> Option<i32> {
+        match self {
+            Tile::CLEAN | Tile::TARGET | Tile::KEY => Some(1),
+            Tile::DIRTY => Some(2),
+            Tile::IMPASSABLE | Tile::DOOR => None,
+        }
+    }
+
+    // Whether an agent with the given state is able to stand on this tile
+    pub fn is_passable_for(&self, state: &AgentState) -> bool {
+        match self {
+            Tile::DOOR => state.has(KEY_ITEM),
+            _ => self.is_passable(),
+        }
+    }
+
+    // Cost of moving onto this tile for an agent with the given state, an open door costs the same as a clean tile
+    pub fn movement_cost_for(&self, state: &AgentState) -> Option<i32> {
+        match self {
+            Tile::DOOR if self.is_passable_for(state) => Some(1),
+            _ => self.movement_cost(),
+        }
+    }
+}
+
+/**
+ * Movement cost of entering each tile type, None for tiles that cannot be entered.
+ * Tiles without an override cost their Tile::movement_cost, so the default model matches it exactly.
+ */
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct CostModel {
+    overrides: HashMap<Tile, Option<i32>>,
+}
+
+impl CostModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cost(&self, tile: &Tile) -> Option<i32> {
+        self.overrides
+            .get(tile)
+            .copied()
+            .unwrap_or_else(|| tile.movement_cost())
+    }
+
+    // Panics if the cost is below 1, since searches and their heuristics rely on every move costing at least 1
+    pub fn set_cost(&mut self, tile: Tile, cost: Option<i32>) {
+        if let Some(cost) = cost {
+            assert!(cost >= 1, "tile costs must be at least 1, got {}", cost);
+        }
+        self.overrides.insert(tile, cost);
+    }
+
+    // Builder form of set_cost
+    pub fn with_cost(mut self, tile: Tile, cost: Option<i32>) -> Self {
+        self.set_cost(tile, cost);
+        self
+    }
+}
+
+/**
+ * How map files are parsed.
+ * By default whitespace around each line is trimmed and any space left inside a line is an unknown tile, so maps that
+ * use spaces as tiles should turn trimming off and set the tile spaces stand for.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LoadOptions {
+    pub trim_whitespace: bool,
+    pub space_tile: Option<Tile>,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        LoadOptions {
+            trim_whitespace: true,
+            space_tile: None,
+        }
+    }
+}
+
+impl LoadOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_trim_whitespace(&mut self, trim_whitespace: bool) {
+        self.trim_whitespace = trim_whitespace;
+    }
+
+    // Builder form of set_trim_whitespace
+    pub fn with_trim_whitespace(mut self, trim_whitespace: bool) -> Self {
+        self.set_trim_whitespace(trim_whitespace);
+        self
+    }
+
+    // Tile a space character is parsed as, None to reject spaces as unknown tiles
+    pub fn set_space_tile(&mut self, space_tile: Option<Tile>) {
+        self.space_tile = space_tile;
+    }
+
+    // Builder form of set_space_tile
+    pub fn with_space_tile(mut self, space_tile: Option<Tile>) -> Self {
+        self.set_space_tile(space_tile);
+        self
+    }
+}
+
+/**
+ * Errors that can occur while loading or comparing maps.
+ */
+#[derive(Debug)]
+pub enum MapError {
+    Io(std::io::Error),
+    // The input is empty or contains only whitespace
+    Empty,
+    // A character that does not correspond to any tile, with its position in the map
+    UnknownTile { character: char, position: IVec2 },
+    // Two maps that must be the same size are not, with the dimensions of each
+    DimensionMismatch { left: IVec2, right: IVec2 },
+}
+
+impl Display for MapError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapError::Io(error) => write!(f, "{}", error),
+            MapError::Empty => write!(f, "The file is empty or contains only whitespace."),
+            MapError::UnknownTile {
+                character,
+                position,
+            } => write!(f, "Unknown tile character: {} at {}", character, position),
+            MapError::DimensionMismatch { left, right } => write!(
+                f,
+                "Maps differ in size: {}x{} vs {}x{}",
+                left.x, left.y, right.x, right.y
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+impl From<std::io::Error> for MapError {
+    fn from(error: std::io::Error) -> Self {
+        MapError::Io(error)
+    }
+}
+
+/**
+ * Invariant a scenario can require of its map, checked by Map::validate.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ValidationRule {
+    ExactlyOneTarget,
+    // Every cell on the outer edge of the map is impassable
+    SealedBorder,
+    AtLeastOnePassable,
+}
+
+/**
+ * Violation of a validation rule.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ValidationError {
+    // The map does not have exactly one target, with the number it has
+    TargetCount(usize),
+    OpenBorder,
+    NoPassableTiles,
+}
+
+impl ValidationError {
+    // Rule that was violated
+    pub fn rule(&self) -> ValidationRule {
+        match self {
+            ValidationError::TargetCount(_) => ValidationRule::ExactlyOneTarget,
+            ValidationError::OpenBorder => ValidationRule::SealedBorder,
+            ValidationError::NoPassableTiles => ValidationRule::AtLeastOnePassable,
+        }
+    }
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::TargetCount(count) => {
+                write!(f, "Expected exactly one target, found {}.", count)
+            }
+            ValidationError::OpenBorder => write!(f, "The map border has passable tiles."),
+            ValidationError::NoPassableTiles => write!(f, "The map has no passable tiles."),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/**
+ * Rule for diagonal moves squeezing past wall corners.
+ * A diagonal move passes between two orthogonal cells, which may be walls or off the map.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum CornerCutting {
+    // Diagonal moves are always allowed
+    Allow,
+    // Diagonal moves are blocked if either orthogonal cell is blocked
+    #[default]
+    Disallow,
+    // Diagonal moves are blocked only if both orthogonal cells are blocked
+    RequireOneClear,
+}
+
+/**
+ * How neighbor generation treats positions past the edge of the map.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum WrapMode {
+    // Positions past an edge are clamped back onto it
+    Clamp,
+    // Opposite edges connect, making the map a torus
+    Wrap,
+    // Positions past an edge do not exist
+    #[default]
+    None,
+}
+
+/**
+ * How a neighbor is reached from the tile it neighbors.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeKind {
+    // A single move in a direction
+    Step(Direction),
+    // Through a portal to its exit
+    Teleport,
+}
+
+/**
+ * Order to visit every cell of a map in.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum TraversalOrder {
+    // Left to right along each row, top row first
+    #[default]
+    RowMajor,
+    // Top to bottom along each column, left column first
+    ColumnMajor,
+    // Clockwise spiral starting at the center, which rounds up and to the left on even sizes
+    SpiralOut,
+    // SpiralOut reversed, so the center comes last
+    SpiralIn,
+}
+
+/**
+ * Layers a map holds tiles on, from bottom to top.
+ * Every cell has a terrain tile, while the object layer is sparse and only covers the cells it has been set on.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, Debug)]
+pub enum Layer {
+    #[default]
+    Terrain,
+    Objects,
+}
+
+/**
+ * Axis a map can be symmetric about.
+ * Horizontal mirrors left to right, Vertical mirrors top to bottom.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Symmetry {
+    Horizontal,
+    Vertical,
+    Rotational180,
+}
+
+/**
+ * Summary statistics for a map, useful for tuning procedural generation.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct MapStats {
+    pub width: usize,
+    pub height: usize,
+    pub counts: HashMap<Tile, usize>,
+    // Fraction of all tiles that are impassable
+    pub wall_ratio: f32,
+}
+
+/**
+ * Terrain crossed by a path.
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PathSummary {
+    // Tiles of each type along the path, including the start
+    pub counts: HashMap<Tile, usize>,
+    // Sum of the movement costs of every tile entered after the start, None if a tile cannot be entered
+    pub total_cost: Option<i32>,
+}
+
+/**
+ * Change to the tile get_tile returns at a single position, on whichever layer it was made.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileMutation {
+    pub pos: IVec2,
+    pub old: Tile,
+    pub new: Tile,
+}
+
+/**
+ * Visible tile changes made through set_tile, the layer functions, get_tile_mut and the functions built on them,
+ * in the order they were made. Edits hidden beneath an object and changes to the map's size are not recorded,
+ * and entries keep the positions they were recorded at when the map is later resized.
+ */
+#[derive(Clone, Default, Debug)]
+pub struct MutationLog {
+    mutations: Vec<TileMutation>,
+    // Tiles handed out by get_tile_mut and their value at the time, compared once the write can have happened
+    pending: Vec<(IVec2, Tile)>,
+}
+
+impl MutationLog {
+    pub fn len(&self) -> usize {
+        self.mutations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mutations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TileMutation> {
+        self.mutations.iter()
+    }
+
+    // Returns every recorded change, clearing the log
+    pub fn drain(&mut self) -> Vec<TileMutation> {
+        std::mem::take(&mut self.mutations)
+    }
+}
+
+/**
+ * Map keyed by position that iterates in row-major order, so results are the same on every run.
+ * IVec2 has no ordering of its own, so entries are stored under (y, x).
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GridMap<V> {
+    entries: BTreeMap<(i32, i32), V>,
+}
+
+// Row-major sort key of a position
+fn grid_key(pos: &IVec2) -> (i32, i32) {
+    (pos.y, pos.x)
+}
+
+fn grid_pos((y, x): (i32, i32)) -> IVec2 {
+    IVec2::new(x, y)
+}
+
+impl<V> Default for GridMap<V> {
+    fn default() -> Self {
+        GridMap {
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl<V> GridMap<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, pos: &IVec2) -> Option<&V> {
+        self.entries.get(&grid_key(pos))
+    }
+
+    pub fn get_mut(&mut self, pos: &IVec2) -> Option<&mut V> {
+        self.entries.get_mut(&grid_key(pos))
+    }
+
+    pub fn contains_key(&self, pos: &IVec2) -> bool {
+        self.entries.contains_key(&grid_key(pos))
+    }
+
+    // Returns the value previously at the position, if any
+    pub fn insert(&mut self, pos: IVec2, value: V) -> Option<V> {
+        self.entries.insert(grid_key(&pos), value)
+    }
+
+    pub fn remove(&mut self, pos: &IVec2) -> Option<V> {
+        self.entries.remove(&grid_key(pos))
+    }
+
+    pub fn retain(&mut self, mut keep: impl FnMut(&IVec2, &mut V) -> bool) {
+        self.entries
+            .retain(|key, value| keep(&grid_pos(*key), value));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (IVec2, &V)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (grid_pos(*key), value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = IVec2> + '_ {
+        self.entries.keys().map(|key| grid_pos(*key))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.values()
+    }
+
+    pub fn into_keys(self) -> impl Iterator<Item = IVec2> {
+        self.entries.into_keys().map(grid_pos)
+    }
+
+    pub fn into_values(self) -> impl Iterator<Item = V> {
+        self.entries.into_values()
+    }
 }
 
-#[derive(Clone)]
+impl<V> FromIterator<(IVec2, V)> for GridMap<V> {
+    fn from_iter<I: IntoIterator<Item = (IVec2, V)>>(iter: I) -> Self {
+        GridMap {
+            entries: iter
+                .into_iter()
+                .map(|(pos, value)| (grid_key(&pos), value))
+                .collect(),
+        }
+    }
+}
+
+impl<V> IntoIterator for GridMap<V> {
+    type Item = (IVec2, V);
+    type IntoIter =
+        std::iter::Map<btree_map::IntoIter<(i32, i32), V>, fn(((i32, i32), V)) -> (IVec2, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries
+            .into_iter()
+            .map(|(key, value)| (grid_pos(key), value))
+    }
+}
+
+// Largest number of passable tiles all_pairs_distances will run on, about 16 million pairs
+pub const ALL_PAIRS_MAX_TILES: usize = 4096;
+
+#[derive(Clone, Default, Debug)]
 pub struct Map {
     tiles: Vec<Vec<Tile>>,
+    // Object layer tiles drawn above the terrain, cells without an object show their terrain
+    objects: HashMap<IVec2, Tile>,
+    // Portal entrances mapped to their exits
+    portals: HashMap<IVec2, IVec2>,
+    // One-way tiles mapped to the only direction they can be left in
+    one_way: HashMap<IVec2, Direction>,
+    corner_cutting: CornerCutting,
+    wrap_mode: WrapMode,
+    // XOR of every terrain cell hash, except cells handed out by get_tile_mut which are hashed on demand
+    checksum: u64,
+    unhashed: HashSet<IVec2>,
+    // Only kept while mutation logging is enabled, so maps that are never observed do not grow a log
+    mutation_log: Option<MutationLog>,
 }
 
 impl Map {
     pub fn new(width: usize, height: usize) -> Self {
-        let map = Map {
-            tiles: vec![vec![Tile::default(); width]; height],
-        };
+        Self::from_tiles(vec![vec![Tile::default(); width]; height])
+    }
+
+    // Builds a map by calling f for the tile at every position
+    pub fn from_fn<F: Fn(IVec2) -> Tile>(width: usize, height: usize, f: F) -> Map {
+        Self::from_tiles(
+            (0..height)
+                .map(|y| {
+                    (0..width)
+                        .map(|x| f(IVec2::new(x as i32, y as i32)))
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    // Generates organic caverns by seeding random walls and smoothing them with the 4-5 cellular automaton rule
+    // Only the largest connected open region is kept so the whole cave is reachable
+    pub fn generate_cave(
+        width: usize,
+        height: usize,
+        initial_wall_ratio: f32,
+        iterations: usize,
+        seed: u64,
+    ) -> Map {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut map = Map::from_fn(width, height, |_| Tile::CLEAN);
+        for y in 0..height {
+            for x in 0..width {
+                if rng.random::<f32>() < initial_wall_ratio {
+                    map.set_tile(IVec2::new(x as i32, y as i32), Tile::IMPASSABLE);
+                }
+            }
+        }
+
+        for _ in 0..iterations {
+            let previous = map.clone();
+            for (pos, _tile) in previous.get_tile_iterator() {
+                let block = previous.get_block(&pos);
+                // Cells off the map count as walls
+                let walls = 9 - block.len()
+                    + block
+                        .values()
+                        .filter(|tile| ***tile == Tile::IMPASSABLE)
+                        .count();
+                let tile = if walls >= 5 {
+                    Tile::IMPASSABLE
+                } else {
+                    Tile::CLEAN
+                };
+                map.set_tile(pos, tile);
+            }
+        }
+
+        let labels = map.label_regions();
+        let mut sizes: HashMap<usize, usize> = HashMap::new();
+        for label in labels.values() {
+            *sizes.entry(*label).or_insert(0) += 1;
+        }
+        let largest = sizes
+            .into_iter()
+            .max_by_key(|(label, size)| (*size, std::cmp::Reverse(*label)))
+            .map(|(label, _)| label);
+        for (pos, label) in labels {
+            if Some(label) != largest {
+                map.set_tile(pos, Tile::IMPASSABLE);
+            }
+        }
+        map
+    }
 
+    fn from_tiles(tiles: Vec<Vec<Tile>>) -> Self {
+        let mut map = Map {
+            tiles,
+            ..Default::default()
+        };
+        map.checksum = map
+            .get_tile_iterator()
+            .fold(0, |checksum, (pos, tile)| checksum ^ cell_hash(pos, tile));
         map
     }
 
-    pub fn load_from_file(filename: &str) -> Result<Self, std::io::Error> {
+    pub fn load_from_file(filename: &str) -> Result<Self, MapError> {
+        Self::load_from_file_with(filename, LoadOptions::default())
+    }
+
+    // Loads a map file parsed with the given options
+    pub fn load_from_file_with(filename: &str, options: LoadOptions) -> Result<Self, MapError> {
         let file = File::open(filename)?;
-        let reader = BufReader::new(file);
+        Self::load_from_reader_with(BufReader::new(file), options)
+    }
+
+    // Parses a map from any buffered reader, such as a file or an in-memory byte slice
+    // Whitespace around each line is ignored
+    pub fn load_from_reader<R: BufRead>(reader: R) -> Result<Self, MapError> {
+        Self::load_from_reader_with(reader, LoadOptions::default())
+    }
+
+    // Parses a map, only trimming whitespace around each line if the options ask for it
+    // Line endings, both LF and CRLF, are always stripped, so untrimmed lines keep any spaces as tile characters
+    // Spaces are an UnknownTile error unless the options give a tile for them
+    pub fn load_from_reader_with<R: BufRead>(
+        reader: R,
+        options: LoadOptions,
+    ) -> Result<Self, MapError> {
         let mut lines: Vec<String> = Vec::new();
 
         for line_result in reader.lines() {
             let line = line_result?;
-            // Trim leading and trailing whitespace
-            let trimmed_line = line.trim();
+            // lines() leaves the carriage return of a CRLF ending on a final line without a newline
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            let trimmed_line = if options.trim_whitespace {
+                line.trim()
+            } else {
+                line
+            };
             if !trimmed_line.is_empty() {
                 lines.push(trimmed_line.to_string());
             }
         }
 
         if lines.is_empty() {
-            return Err(std::io::Error::new(
-                ErrorKind::Other,
-                "The file is empty or contains only whitespace.",
-            ));
+            return Err(MapError::Empty);
         }
 
         let height = lines.len();
         let mut tiles = Vec::with_capacity(height);
 
-        for line in lines {
+        for (y, line) in lines.into_iter().enumerate() {
             let mut row: Vec<Tile> = Vec::with_capacity(line.len());
-            for c in line.chars() {
+            for (x, c) in line.chars().enumerate() {
                 match c {
                     'C' => row.push(Tile::CLEAN),
+                    'D' => row.push(Tile::DIRTY),
                     'W' => row.push(Tile::IMPASSABLE),
                     'T' => row.push(Tile::TARGET),
+                    'K' => row.push(Tile::KEY),
+                    'L' => row.push(Tile::DOOR),
+                    ' ' if options.space_tile.is_some() => row.extend(options.space_tile),
                     _ => {
                         // Return an error for unexpected characters
-                        return Err(std::io::Error::new(
-                            ErrorKind::Other,
-                            format!("Unknown tile character: {}", c),
-                        ));
+                        return Err(MapError::UnknownTile {
+                            character: c,
+                            position: IVec2::new(x as i32, y as i32),
+                        });
                     }
                 }
             }
             tiles.push(row);
         }
 
-        Ok(Map { tiles })
+        Ok(Self::from_tiles(tiles))
+    }
+
+    pub fn width(&self) -> usize {
+        self.tiles.first().map_or(0, |row| row.len())
+    }
+
+    pub fn height(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Width and height as a vector, so bounds checks can be written as pos.cmplt(map.dimensions()).all()
+    pub fn dimensions(&self) -> IVec2 {
+        IVec2::new(self.width() as i32, self.height() as i32)
     }
 
     pub fn has_tile(&self, pos: IVec2) -> bool {
@@ -89,67 +685,2508 @@ impl Map {
             .is_some()
     }
 
-    pub fn get_tile(&self, pos: IVec2) -> Option<&Tile> {
-        self.tiles.get(pos.y as usize)?.get(pos.x as usize)
+    // Converts a position to its row-major index, None if the position is off the map
+    pub fn to_index(&self, pos: IVec2) -> Option<usize> {
+        if pos.x < 0 || pos.y < 0 || !self.has_tile(pos) {
+            return None;
+        }
+        Some(pos.y as usize * self.width() + pos.x as usize)
     }
 
-    pub fn get_tile_mut(&mut self, pos: IVec2) -> &mut Tile {
-        &mut self.tiles[pos.y as usize][pos.x as usize]
+    // Converts a row-major index back to a position, the index is not bounds checked
+    pub fn from_index(&self, idx: usize) -> IVec2 {
+        let width = self.width().max(1);
+        IVec2::new((idx % width) as i32, (idx / width) as i32)
     }
 
-    pub fn get_tile_iterator(&self) -> impl Iterator<Item = (IVec2, &Tile)> {
-        self.tiles.iter().enumerate().flat_map(|(y, row)| {
-            row.iter()
-                .enumerate()
-                .map(move |(x, tile)| (IVec2::new(x as i32, y as i32), tile))
-        })
+    // Gets the tile on the topmost layer that has one at the position
+    pub fn get_tile(&self, pos: IVec2) -> Option<&Tile> {
+        self.objects
+            .get(&pos)
+            .or_else(|| self.get_layer_tile(Layer::Terrain, pos))
     }
 
-    pub fn get_line_iterator(&self) -> impl Iterator<Item = Vec<(IVec2, &Tile)>> {
-        self.tiles.iter().enumerate().map(|(y, row)| {
-            row.iter().enumerate().map(move |(x, tile)| {
-                (IVec2::new(x as i32, y as i32), tile)
-            }).collect::<Vec<_>>()
-        })
+    // Gets the tile on a single layer, None if that layer is empty at the position
+    pub fn get_layer_tile(&self, layer: Layer, pos: IVec2) -> Option<&Tile> {
+        match layer {
+            Layer::Terrain => self.tiles.get(pos.y as usize)?.get(pos.x as usize),
+            Layer::Objects => self.objects.get(&pos),
+        }
     }
 
-    // Gets all of the tiles of a certain type
-    pub fn get_all_of_type(&self, tile_type: Tile) -> HashMap<IVec2, &Tile> {
-        self.get_tile_iterator().filter(|&(_, tile)| tile == &tile_type).collect()
+    // Sets the tile on a single layer, positions off the map are ignored
+    pub fn set_layer_tile(&mut self, layer: Layer, pos: IVec2, tile: Tile) {
+        if !self.has_tile(pos) {
+            return;
+        }
+        match layer {
+            Layer::Terrain => self.set_terrain_tile(pos, tile),
+            Layer::Objects => {
+                self.set_object_tile(pos, Some(tile));
+            }
+        }
     }
 
-    // Returns neighbors of a given tile
-    pub fn get_neighbors(&self, pos: &IVec2) -> HashMap<IVec2, (Direction, &Tile)> {
-        let mut neighbors = HashMap::new();
-        for direction in Direction::all() {
-            let neighbor_pos = pos + direction.to_ivec2();
-
-            if let Some(tile) = self.get_tile(neighbor_pos) {
-                neighbors.insert(neighbor_pos, (direction, tile));
+    // Empties a layer at the position, returning the tile removed
+    // The terrain layer cannot be empty, so it is reset to the default tile instead
+    pub fn clear_layer_tile(&mut self, layer: Layer, pos: IVec2) -> Option<Tile> {
+        match layer {
+            Layer::Terrain => {
+                let old = *self.get_layer_tile(Layer::Terrain, pos)?;
+                self.set_terrain_tile(pos, Tile::default());
+                Some(old)
             }
+            Layer::Objects => self.set_object_tile(pos, None),
         }
-        neighbors
     }
 
-    pub fn set_tile(&mut self, pos: IVec2, tile: Tile) {
-        self.tiles[pos.y as usize][pos.x as usize] = tile;
+    // Places or removes the object at a position, returning the object it replaced
+    fn set_object_tile(&mut self, pos: IVec2, tile: Option<Tile>) -> Option<Tile> {
+        self.flush_pending_mutations();
+        let old = self.get_tile(pos).copied();
+        let replaced = match tile {
+            Some(tile) => self.objects.insert(pos, tile),
+            None => self.objects.remove(&pos),
+        };
+        if let Some((old, new)) = old.zip(self.get_tile(pos).copied()) {
+            self.log_change(pos, old, new);
+        }
+        replaced
     }
-}
 
-impl Display for Map {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut output = String::new();
-        for row in &self.tiles {
-            for tile in row {
-                match tile {
-                    Tile::IMPASSABLE => output.push('W'),
-                    Tile::CLEAN => output.push('C'),
-                    Tile::DIRTY => output.push('D'),
-                    Tile::TARGET => output.push('T'),
-                }
+    // Whether the position is on the map and its tile is passable
+    pub fn is_walkable(&self, pos: IVec2) -> bool {
+        self.get_tile(pos).is_some_and(|tile| tile.is_passable())
+    }
+
+    // Mutable access to the tile returned by get_tile, the object at the position if there is one
+    pub fn get_tile_mut(&mut self, pos: IVec2) -> &mut Tile {
+        if let Some(log) = self.mutation_log.as_mut() {
+            if !log.pending.iter().any(|(pending, _)| *pending == pos) {
+                let tile = self
+                    .objects
+                    .get(&pos)
+                    .unwrap_or(&self.tiles[pos.y as usize][pos.x as usize]);
+                log.pending.push((pos, *tile));
             }
-            output.push('\n');
         }
-        write!(f, "{}", output)
+        if self.objects.contains_key(&pos) {
+            return self.objects.get_mut(&pos).unwrap();
+        }
+        // The tile may change behind our back, so it is hashed on demand until the next set_tile
+        if self.unhashed.insert(pos) {
+            self.checksum ^= cell_hash(pos, &self.tiles[pos.y as usize][pos.x as usize]);
+        }
+        &mut self.tiles[pos.y as usize][pos.x as usize]
+    }
+
+    // Iterates the tiles returned by get_tile in row-major order
+    pub fn get_tile_iterator(&self) -> impl Iterator<Item = (IVec2, &Tile)> {
+        self.tiles.iter().enumerate().flat_map(move |(y, row)| {
+            row.iter().enumerate().map(move |(x, tile)| {
+                let pos = IVec2::new(x as i32, y as i32);
+                (pos, self.objects.get(&pos).unwrap_or(tile))
+            })
+        })
+    }
+
+    pub fn get_line_iterator(&self) -> impl Iterator<Item = Vec<(IVec2, &Tile)>> {
+        self.tiles.iter().enumerate().map(move |(y, row)| {
+            row.iter()
+                .enumerate()
+                .map(move |(x, tile)| {
+                    let pos = IVec2::new(x as i32, y as i32);
+                    (pos, self.objects.get(&pos).unwrap_or(tile))
+                })
+                .collect::<Vec<_>>()
+        })
+    }
+
+    // Iterates every tile returned by get_tile in the given order
+    pub fn iter_order(&self, order: TraversalOrder) -> impl Iterator<Item = (IVec2, &Tile)> {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        let positions: Vec<IVec2> = match order {
+            TraversalOrder::RowMajor => (0..height)
+                .flat_map(|y| (0..width).map(move |x| IVec2::new(x, y)))
+                .collect(),
+            TraversalOrder::ColumnMajor => (0..width)
+                .flat_map(|x| (0..height).map(move |y| IVec2::new(x, y)))
+                .collect(),
+            TraversalOrder::SpiralOut => self.spiral_positions(),
+            TraversalOrder::SpiralIn => {
+                let mut positions = self.spiral_positions();
+                positions.reverse();
+                positions
+            }
+        };
+        positions
+            .into_iter()
+            .filter_map(|pos| Some((pos, self.get_tile(pos)?)))
+    }
+
+    // Walks outward from the center in runs of 1, 1, 2, 2, 3, 3... turning clockwise after each run
+    // Positions off the map are skipped until every cell has been visited
+    fn spiral_positions(&self) -> Vec<IVec2> {
+        let count = self.get_tile_iterator().count();
+        let mut positions = Vec::with_capacity(count);
+        if count == 0 {
+            return positions;
+        }
+        let mut current = IVec2::new(
+            (self.width() as i32 - 1) / 2,
+            (self.height() as i32 - 1) / 2,
+        );
+        let turns = [
+            Direction::Right,
+            Direction::Down,
+            Direction::Left,
+            Direction::Up,
+        ];
+        let mut run = 1;
+        'spiral: loop {
+            for (turn, direction) in turns.iter().enumerate() {
+                for _ in 0..run {
+                    if self.has_tile(current) {
+                        positions.push(current);
+                        if positions.len() == count {
+                            break 'spiral;
+                        }
+                    }
+                    current += direction.to_ivec2();
+                }
+                // Runs grow after every second turn
+                if turn % 2 == 1 {
+                    run += 1;
+                }
+            }
+        }
+        positions
+    }
+
+    // Gets all of the tiles of a certain type
+    pub fn get_all_of_type(&self, tile_type: Tile) -> GridMap<&Tile> {
+        self.get_tile_iterator().filter(|&(_, tile)| tile == &tile_type).collect()
+    }
+
+    // Gets the positions of every target in row-major order
+    // Maps may hold several targets, callers wanting a single goal conventionally take the first
+    pub fn targets(&self) -> Vec<IVec2> {
+        self.get_tile_iterator()
+            .filter(|(_, tile)| **tile == Tile::TARGET)
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    // Returns neighbors of a given tile
+    // This is the grid's shape, so one-way tiles are ignored, moves_iter and the pathfinders honor them
+    // Neighbors past the edge of the map follow the wrap mode, a tile is never its own neighbor
+    pub fn get_neighbors(&self, pos: &IVec2) -> GridMap<(Direction, &Tile)> {
+        self.neighbors_iter(*pos)
+            .map(|(neighbor_pos, direction, tile)| (neighbor_pos, (direction, tile)))
+            .collect()
+    }
+
+    // Yields the same neighbors as get_neighbors lazily in the order of Direction::all(), without allocating
+    pub fn neighbors_iter(&self, pos: IVec2) -> impl Iterator<Item = (IVec2, Direction, &Tile)> {
+        Direction::all().into_iter().filter_map(move |direction| {
+            // Clamping at an edge leads back onto the same tile, which is not a move
+            let neighbor_pos = self
+                .step(pos, direction, self.wrap_mode)
+                .filter(|neighbor_pos| *neighbor_pos != pos)?;
+            Some((neighbor_pos, direction, self.get_tile(neighbor_pos)?))
+        })
+    }
+
+    // Neighbors an agent standing on the tile can step to, like neighbors_iter but honoring one-way tiles
+    pub fn moves_iter(&self, pos: IVec2) -> impl Iterator<Item = (IVec2, Direction, &Tile)> {
+        self.neighbors_iter(pos)
+            .filter(move |(_, direction, _)| self.can_leave(&pos, *direction))
+    }
+
+    // Returns neighbors of a given tile, skipping any position in the exclude set such as occupied tiles
+    pub fn get_neighbors_excluding(
+        &self,
+        pos: &IVec2,
+        exclude: &HashSet<IVec2>,
+    ) -> GridMap<(Direction, &Tile)> {
+        let mut neighbors = self.get_neighbors(pos);
+        neighbors.retain(|neighbor_pos, _| !exclude.contains(neighbor_pos));
+        neighbors
+    }
+
+    // Returns the on-map tiles reached by adding each offset to a position, in the order of the offsets
+    // Offsets can jump over other tiles, so custom movement such as knight moves ignore one-way tiles and wrapping
+    pub fn neighbors_by_offsets(&self, pos: IVec2, offsets: &[IVec2]) -> Vec<(IVec2, &Tile)> {
+        offsets
+            .iter()
+            .filter_map(|offset| {
+                let neighbor_pos = pos + *offset;
+                Some((neighbor_pos, self.get_tile(neighbor_pos)?))
+            })
+            .collect()
+    }
+
+    // View of the map that pathfinders search moving only by the given offsets
+    pub fn with_offsets<'a>(&'a self, offsets: &'a [IVec2]) -> OffsetView<'a> {
+        OffsetView::new(self, offsets)
+    }
+
+    // Draws the map as text as configured by the options, Display uses the defaults
+    pub fn render_with(&self, opts: &RenderOptions) -> String {
+        draw_with_options(self, opts, Vec::new())
+    }
+
+    // Makes a tile one-way, so it can only be left in the given direction
+    pub fn set_one_way(&mut self, pos: IVec2, direction: Direction) {
+        self.one_way.insert(pos, direction);
+    }
+
+    pub fn clear_one_way(&mut self, pos: IVec2) -> Option<Direction> {
+        self.one_way.remove(&pos)
+    }
+
+    // Gets the only direction a one-way tile can be left in, if the tile is one-way
+    pub fn get_one_way(&self, pos: &IVec2) -> Option<Direction> {
+        self.one_way.get(pos).copied()
+    }
+
+    // Whether a move in the given direction is allowed to leave the tile
+    pub fn can_leave(&self, pos: &IVec2, direction: Direction) -> bool {
+        self.one_way
+            .get(pos)
+            .is_none_or(|allowed| *allowed == direction)
+    }
+
+    pub fn corner_cutting(&self) -> CornerCutting {
+        self.corner_cutting
+    }
+
+    pub fn set_corner_cutting(&mut self, rule: CornerCutting) {
+        self.corner_cutting = rule;
+    }
+
+    pub fn wrap_mode(&self) -> WrapMode {
+        self.wrap_mode
+    }
+
+    // Sets how get_neighbors, and so every pathfinder, treats the edges of the map
+    pub fn set_wrap_mode(&mut self, wrap_mode: WrapMode) {
+        self.wrap_mode = wrap_mode;
+    }
+
+    // Position reached by moving one tile in a direction, with the edges of the map handled by wrap_mode
+    // Only the edges are checked, the tile reached may still be impassable
+    pub fn step(&self, pos: IVec2, direction: Direction, wrap_mode: WrapMode) -> Option<IVec2> {
+        self.apply_wrap(pos + direction.to_ivec2(), wrap_mode)
+    }
+
+    // Maps a position that may be past the edge of the map back onto it, None if it stays off the map
+    fn apply_wrap(&self, pos: IVec2, wrap_mode: WrapMode) -> Option<IVec2> {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        match wrap_mode {
+            WrapMode::Clamp => Some(pos.clamp(IVec2::ZERO, IVec2::new(width - 1, height - 1))),
+            WrapMode::Wrap => Some(IVec2::new(
+                pos.x.rem_euclid(width),
+                pos.y.rem_euclid(height),
+            )),
+            WrapMode::None => self.has_tile(pos).then_some(pos),
+        }
+    }
+
+    // Labels each passable tile with the id of its 4-connected region, ids are assigned in row-major order
+    pub fn label_regions(&self) -> GridMap<usize> {
+        let mut labels = GridMap::new();
+        let mut next_label = 0;
+        for (start, tile) in self.get_tile_iterator() {
+            if !tile.is_passable() || labels.contains_key(&start) {
+                continue;
+            }
+            labels.insert(start, next_label);
+            let mut stack = vec![start];
+            while let Some(current) = stack.pop() {
+                for (neighbor, _direction, tile) in self.neighbors_iter(current) {
+                    if tile.is_passable() && !labels.contains_key(&neighbor) {
+                        labels.insert(neighbor, next_label);
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            next_label += 1;
+        }
+        labels
+    }
+
+    // Returns passable neighbors that can be moved to ordered by ascending manhattan distance to the target
+    // Ties keep the order of Direction::all(), edges of the map follow the wrap mode as in moves_iter
+    pub fn neighbors_sorted_toward(
+        &self,
+        pos: &IVec2,
+        target: IVec2,
+    ) -> Vec<(IVec2, Direction, &Tile)> {
+        let mut neighbors: Vec<(IVec2, Direction, &Tile)> = self
+            .moves_iter(*pos)
+            .filter(|(_, _, tile)| tile.is_passable())
+            .collect();
+        neighbors.sort_by_key(|(neighbor_pos, _, _)| manhattan_distance(neighbor_pos, &target));
+        neighbors
+    }
+
+    // How many of the 4 cardinal neighbors are passable, 1 for a dead end and 2 for a straight corridor
+    // This describes the shape of the grid, so one-way tiles are ignored
+    pub fn open_neighbor_count(&self, pos: IVec2) -> u8 {
+        self.passable_mask(pos).count_ones() as u8
+    }
+
+    // Bit mask of the passable cardinal neighbors, bit i is set for the i-th direction of Direction::all()
+    // So up is 1, down is 2, left is 4 and right is 8, a horizontal corridor being 0b1100
+    // Edges of the map follow the wrap mode, as in neighbors_iter
+    pub fn passable_mask(&self, pos: IVec2) -> u8 {
+        Direction::all()
+            .into_iter()
+            .enumerate()
+            .filter(|(_, direction)| {
+                self.step(pos, *direction, self.wrap_mode)
+                    .is_some_and(|neighbor| neighbor != pos && self.is_walkable(neighbor))
+            })
+            .fold(0, |mask, (bit, _)| mask | 1 << bit)
+    }
+
+    // Actions an agent standing at pos could usefully take, moves to each passable neighbor in Direction::all() order
+    // that a one-way tile allows, followed by waiting, cleaning when the tile is dirty, and teleporting from a portal
+    pub fn available_actions(&self, pos: IVec2) -> Vec<Action> {
+        let mut actions: Vec<Action> = self
+            .moves_iter(pos)
+            .filter(|(_, _, tile)| tile.is_passable())
+            .map(|(_, direction, _)| Action::Move { direction })
+            .collect();
+        actions.push(Action::Wait);
+        if self.get_tile(pos) == Some(&Tile::DIRTY) {
+            actions.push(Action::Clean);
+        }
+        if self.get_portal(&pos).is_some() {
+            actions.push(Action::Teleport);
+        }
+        actions
+    }
+
+    // The first walkable cardinal neighbor in the order of Direction::all(), None if boxed in
+    // Edges of the map follow the wrap mode as in neighbors_iter
+    pub fn first_walkable_neighbor(&self, pos: IVec2) -> Option<IVec2> {
+        self.neighbors_iter(pos)
+            .find(|(_, _, tile)| tile.is_passable())
+            .map(|(neighbor, _, _)| neighbor)
+    }
+
+    // Returns the in-bounds cells of the 3x3 block centered on a tile, including the tile itself
+    pub fn get_block(&self, pos: &IVec2) -> GridMap<&Tile> {
+        let mut block = GridMap::default();
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let block_pos = pos + IVec2::new(x, y);
+                if let Some(tile) = self.get_tile(block_pos) {
+                    block.insert(block_pos, tile);
+                }
+            }
+        }
+        block
+    }
+
+    // Returns all eight neighbors of a given tile, pruning diagonals according to the corner cutting rule
+    // Edges of the map follow the wrap mode and one-way tiles are ignored, as in get_neighbors
+    pub fn get_neighbors8(&self, pos: &IVec2) -> GridMap<(Direction, &Tile)> {
+        let mut neighbors = GridMap::default();
+        for direction in Direction::all8() {
+            if direction.is_diagonal() && !self.diagonal_allowed(pos, direction) {
+                continue;
+            }
+            let Some(neighbor_pos) = self
+                .step(*pos, direction, self.wrap_mode)
+                .filter(|neighbor_pos| neighbor_pos != pos)
+            else {
+                continue;
+            };
+
+            if let Some(tile) = self.get_tile(neighbor_pos) {
+                neighbors.insert(neighbor_pos, (direction, tile));
+            }
+        }
+        neighbors
+    }
+
+    // Returns the passable neighbors among all eight that can be moved to, applying the corner cutting rule
+    // and one-way tiles, grid-locked agents should keep using the cardinal moves_iter
+    pub fn neighbors8_passable(&self, pos: &IVec2) -> GridMap<(Direction, &Tile)> {
+        self.get_neighbors8(pos)
+            .into_iter()
+            .filter(|(_, (direction, tile))| tile.is_passable() && self.can_leave(pos, *direction))
+            .collect()
+    }
+
+    // Checks a diagonal move against the corner cutting rule using the two orthogonal cells it passes between
+    fn diagonal_allowed(&self, pos: &IVec2, direction: Direction) -> bool {
+        let offset = direction.to_ivec2();
+        let is_blocked = |cell: IVec2| {
+            !self
+                .apply_wrap(cell, self.wrap_mode)
+                .and_then(|cell| self.get_tile(cell))
+                .is_some_and(|tile| tile.is_passable())
+        };
+        let horizontal_blocked = is_blocked(pos + IVec2::new(offset.x, 0));
+        let vertical_blocked = is_blocked(pos + IVec2::new(0, offset.y));
+        match self.corner_cutting {
+            CornerCutting::Allow => true,
+            CornerCutting::Disallow => !horizontal_blocked && !vertical_blocked,
+            CornerCutting::RequireOneClear => !horizontal_blocked || !vertical_blocked,
+        }
+    }
+
+    // Sets the tile returned by get_tile, replacing the object at the position if there is one
+    // Use set_layer_tile to write a particular layer
+    pub fn set_tile(&mut self, pos: IVec2, tile: Tile) {
+        if self.objects.contains_key(&pos) {
+            self.set_object_tile(pos, Some(tile));
+        } else {
+            self.set_terrain_tile(pos, tile);
+        }
+    }
+
+    fn set_terrain_tile(&mut self, pos: IVec2, tile: Tile) {
+        self.flush_pending_mutations();
+        let old = &mut self.tiles[pos.y as usize][pos.x as usize];
+        if !self.unhashed.remove(&pos) {
+            self.checksum ^= cell_hash(pos, old);
+        }
+        let old = std::mem::replace(old, tile);
+        self.checksum ^= cell_hash(pos, &tile);
+        if !self.objects.contains_key(&pos) {
+            self.log_change(pos, old, tile);
+        }
+    }
+
+    // Records a change to the visible tile while logging is enabled
+    fn log_change(&mut self, pos: IVec2, old: Tile, new: Tile) {
+        if let Some(log) = self.mutation_log.as_mut().filter(|_| old != new) {
+            log.mutations.push(TileMutation { pos, old, new });
+        }
+    }
+
+    // Logs the tiles handed out by get_tile_mut that have changed since, before anything else is written
+    fn flush_pending_mutations(&mut self) {
+        let Some(log) = self.mutation_log.as_mut() else {
+            return;
+        };
+        for (pos, old) in std::mem::take(&mut log.pending) {
+            let new = *self
+                .objects
+                .get(&pos)
+                .unwrap_or(&self.tiles[pos.y as usize][pos.x as usize]);
+            if old != new {
+                log.mutations.push(TileMutation { pos, old, new });
+            }
+        }
+    }
+
+    // Starts or stops recording tile changes, stopping discards anything not yet drained
+    pub fn set_mutation_logging(&mut self, enabled: bool) {
+        match (enabled, self.mutation_log.is_some()) {
+            (true, false) => self.mutation_log = Some(MutationLog::default()),
+            (false, true) => self.mutation_log = None,
+            _ => {}
+        }
+    }
+
+    // Takes a mutable borrow so writes through get_tile_mut can be logged first
+    pub fn mutation_log(&mut self) -> Option<&MutationLog> {
+        self.flush_pending_mutations();
+        self.mutation_log.as_ref()
+    }
+
+    // Returns the tile changes recorded since the last drain, empty when logging is disabled
+    pub fn drain_mutations(&mut self) -> Vec<TileMutation> {
+        self.flush_pending_mutations();
+        self.mutation_log
+            .as_mut()
+            .map(MutationLog::drain)
+            .unwrap_or_default()
+    }
+
+    // Hash of everything pathfinding depends on: both layers, portals, one-way tiles, corner cutting and wrap mode
+    // Terrain is hashed as tiles are set, the rest is usually small and hashed on each call
+    pub fn checksum(&self) -> u64 {
+        let terrain = self.unhashed.iter().fold(self.checksum, |checksum, pos| {
+            checksum ^ cell_hash(*pos, &self.tiles[pos.y as usize][pos.x as usize])
+        });
+        let objects = self
+            .objects
+            .iter()
+            .map(|(pos, tile)| tagged_hash(OBJECT_TAG, *pos, [*tile as u8]));
+        let portals = self
+            .portals
+            .iter()
+            .map(|(entrance, exit)| tagged_hash(PORTAL_TAG, *entrance, pos_bytes(*exit)));
+        let one_way = self
+            .one_way
+            .iter()
+            .map(|(pos, direction)| tagged_hash(ONE_WAY_TAG, *pos, [*direction as u8]));
+        let settings = fnv_hash([
+            SETTINGS_TAG,
+            self.corner_cutting as u8,
+            self.wrap_mode as u8,
+        ]);
+        objects
+            .chain(portals)
+            .chain(one_way)
+            .fold(terrain ^ settings, |checksum, hash| checksum ^ hash)
+    }
+
+    // Swaps every tile of one type for another, returning how many tiles were changed
+    pub fn replace_tiles(&mut self, from: Tile, to: Tile) -> usize {
+        let positions: Vec<IVec2> = self.get_all_of_type(from).into_keys().collect();
+        for pos in &positions {
+            self.set_tile(*pos, to);
+        }
+        positions.len()
+    }
+
+    // Sets every terrain tile to the given tile and removes the object layer, keeping the dimensions
+    pub fn fill(&mut self, tile: Tile) {
+        let objects: Vec<IVec2> = self.objects.keys().copied().collect();
+        for pos in objects {
+            self.set_object_tile(pos, None);
+        }
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                self.set_tile(IVec2::new(x as i32, y as i32), tile);
+            }
+        }
+    }
+
+    // Resets every cell to a clean tile, such as between repeated experiments
+    pub fn clear(&mut self) {
+        self.fill(Tile::CLEAN);
+    }
+
+    pub fn stats(&self) -> MapStats {
+        let mut counts = HashMap::new();
+        for (_, tile) in self.get_tile_iterator() {
+            *counts.entry(*tile).or_insert(0) += 1;
+        }
+        let total: usize = counts.values().sum();
+        let walls = counts.get(&Tile::IMPASSABLE).copied().unwrap_or(0);
+        MapStats {
+            width: self.width(),
+            height: self.height(),
+            counts,
+            wall_ratio: if total == 0 {
+                0.0
+            } else {
+                walls as f32 / total as f32
+            },
+        }
+    }
+
+    // Counts the tiles a path crosses and totals its movement cost
+    // Positions off the map are not counted and make the total cost None
+    pub fn path_summary(&self, path: &[IVec2]) -> PathSummary {
+        let mut counts = HashMap::new();
+        for tile in path.iter().filter_map(|pos| self.get_tile(*pos)) {
+            *counts.entry(*tile).or_insert(0) += 1;
+        }
+        let total_cost = path
+            .iter()
+            .skip(1)
+            .map(|pos| self.get_tile(*pos).and_then(|tile| tile.movement_cost()))
+            .sum();
+        PathSummary { counts, total_cost }
+    }
+
+    // Links the entrance tile to the exit tile, optionally in both directions
+    pub fn add_portal(&mut self, entrance: IVec2, exit: IVec2, bidirectional: bool) {
+        self.portals.insert(entrance, exit);
+        if bidirectional {
+            self.portals.insert(exit, entrance);
+        }
+    }
+
+    pub fn remove_portal(&mut self, entrance: IVec2) -> Option<IVec2> {
+        self.portals.remove(&entrance)
+    }
+
+    // Gets the exit of a portal at the given position, if there is one
+    pub fn get_portal(&self, pos: &IVec2) -> Option<IVec2> {
+        self.portals.get(pos).copied()
+    }
+
+    // Every portal entrance along with its exit, in no particular order
+    pub fn portals(&self) -> impl Iterator<Item = (IVec2, IVec2)> + Clone + '_ {
+        self.portals
+            .iter()
+            .map(|(entrance, exit)| (*entrance, *exit))
+    }
+
+    // Returns the neighbors a tile can be left to, as in moves_iter, along with the exit of any portal on it
+    // A portal exit that is also an adjacent tile is reported as a step
+    pub fn get_neighbors_with_portals(&self, pos: &IVec2) -> GridMap<(EdgeKind, &Tile)> {
+        let mut neighbors: GridMap<(EdgeKind, &Tile)> = self
+            .moves_iter(*pos)
+            .map(|(neighbor_pos, direction, tile)| {
+                (neighbor_pos, (EdgeKind::Step(direction), tile))
+            })
+            .collect();
+        if let Some((exit, tile)) = self
+            .get_portal(pos)
+            .and_then(|exit| Some((exit, self.get_tile(exit)?)))
+        {
+            if !neighbors.contains_key(&exit) {
+                neighbors.insert(exit, (EdgeKind::Teleport, tile));
+            }
+        }
+        neighbors
+    }
+
+    // View of the map that pathfinders search as an agent carrying the given state, see Tile::movement_cost_for
+    pub fn for_agent<'a>(&'a self, state: &'a AgentState) -> AgentStateView<'a> {
+        AgentStateView::new(self, state)
+    }
+
+    // View of the map that pathfinders search using the given cost model instead of Tile::movement_cost
+    pub fn with_cost_model<'a>(&'a self, model: &'a CostModel) -> CostModelView<'a> {
+        CostModelView::new(self, model)
+    }
+
+    /// Shortest path between two positions counting every move as 1, including both ends
+    /// For weighted or limited searches use the functions in the pathfinding module instead
+    ///
+    /// ```
+    /// use csc411::prelude::*;
+    /// use glam::IVec2;
+    ///
+    /// let map = Map::load_from_reader("CDW\nWCT\n".as_bytes()).unwrap();
+    /// let path = map.astar_to(IVec2::new(0, 0), IVec2::new(2, 1));
+    /// assert_eq!(path, Some(vec![IVec2::new(0, 0), IVec2::new(1, 0), IVec2::new(1, 1), IVec2::new(2, 1)]));
+    /// assert_eq!(map.astar_to(IVec2::new(0, 0), IVec2::new(0, 1)), None);
+    /// ```
+    pub fn astar_to(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        let unit_costs = CostModel::new().with_cost(Tile::DIRTY, Some(1));
+        match astar(&self.with_cost_model(&unit_costs), start, goal, None) {
+            SearchOutcome::Found(path) => Some(path),
+            SearchOutcome::NoPath | SearchOutcome::Exhausted => None,
+        }
+    }
+
+    // Overlays a set of temporarily blocked positions for pathfinding
+    pub fn with_dynamic_obstacles<'a>(&'a self, blocked: &'a Blocked) -> DynamicObstacles<'a> {
+        DynamicObstacles::new(self, blocked)
+    }
+
+    // Size of the largest square of passable tiles with its top-left corner at each position
+    pub fn clearance_map(&self) -> GridMap<u32> {
+        let mut clearance = GridMap::new();
+        for y in (0..self.height() as i32).rev() {
+            for x in (0..self.width() as i32).rev() {
+                let pos = IVec2::new(x, y);
+                let value = if self.get_tile(pos).is_some_and(|tile| tile.is_passable()) {
+                    let at = |offset: IVec2| clearance.get(&(pos + offset)).copied().unwrap_or(0);
+                    1 + at(IVec2::new(1, 0))
+                        .min(at(IVec2::new(0, 1)))
+                        .min(at(IVec2::new(1, 1)))
+                } else {
+                    0
+                };
+                clearance.insert(pos, value);
+            }
+        }
+        clearance
+    }
+
+    // Move counts from each passable tile to every tile it can reach, keyed by source and then by target
+    // Runs a BFS from every passable tile, so time and memory grow with the square of the passable tile count
+    // Panics if the map has more than ALL_PAIRS_MAX_TILES passable tiles
+    pub fn all_pairs_distances(&self) -> GridMap<GridMap<u32>> {
+        let sources: Vec<IVec2> = self
+            .get_tile_iterator()
+            .filter(|(_, tile)| tile.is_passable())
+            .map(|(pos, _)| pos)
+            .collect();
+        assert!(
+            sources.len() <= ALL_PAIRS_MAX_TILES,
+            "all pairs distances limited to {} passable tiles, map has {}",
+            ALL_PAIRS_MAX_TILES,
+            sources.len()
+        );
+
+        let mut distances = GridMap::new();
+        for source in sources {
+            let mut from_source = GridMap::new();
+            let mut frontier = VecDeque::from([(source, 0)]);
+            let mut visited = HashSet::from([source]);
+            while let Some((current, distance)) = frontier.pop_front() {
+                from_source.insert(current, distance);
+                for (neighbor, _cost) in self.neighbors(current) {
+                    if visited.insert(neighbor) {
+                        frontier.push_back((neighbor, distance + 1));
+                    }
+                }
+            }
+            distances.insert(source, from_source);
+        }
+        distances
+    }
+
+    // Whether the goal can be reached from start without entering any tile of the avoided types
+    // The start tile itself is allowed to be one of them, since the agent is already standing on it
+    pub fn path_exists_avoiding(&self, start: IVec2, goal: IVec2, avoid: &[Tile]) -> bool {
+        let mut frontier = VecDeque::from([start]);
+        let mut visited = HashSet::from([start]);
+        while let Some(current) = frontier.pop_front() {
+            if current == goal {
+                return true;
+            }
+            for (neighbor, _cost) in self.neighbors(current) {
+                let avoided = self
+                    .get_tile(neighbor)
+                    .is_some_and(|tile| avoid.contains(tile));
+                if !avoided && visited.insert(neighbor) {
+                    frontier.push_back(neighbor);
+                }
+            }
+        }
+        false
+    }
+
+    // Precomputes neighbor lists for repeated searches over a map that will not change
+    pub fn build_adjacency(&self) -> Adjacency {
+        Adjacency::new(self)
+    }
+
+    // View of the map that pathfinders can search while only entering positions with enough clearance
+    pub fn with_min_clearance(&self, min_clearance: u32) -> ClearanceView<'_> {
+        ClearanceView::new(self, min_clearance)
+    }
+
+    // Resizes the map keeping existing tiles anchored at the top-left, new cells are set to fill
+    pub fn resize(&mut self, new_width: usize, new_height: usize, fill: Tile) {
+        self.resize_with_offset(new_width, new_height, IVec2::ZERO, fill);
+    }
+
+    // Resizes the map keeping existing tiles centered, clipping evenly from each side when shrinking
+    // When the size changes by an odd amount the extra row or column is on the right or bottom
+    pub fn resize_centered(&mut self, new_width: usize, new_height: usize, fill: Tile) {
+        let offset = IVec2::new(
+            (new_width as i32 - self.width() as i32) / 2,
+            (new_height as i32 - self.height() as i32) / 2,
+        );
+        self.resize_with_offset(new_width, new_height, offset, fill);
+    }
+
+    // Surrounds the map with a one tile wide ring of the given tile, shifting existing tiles down and right by one
+    pub fn add_border(&self, tile: Tile) -> Map {
+        let mut map = self.clone();
+        map.resize_with_offset(self.width() + 2, self.height() + 2, IVec2::ONE, tile);
+        map
+    }
+
+    // Removes outer rows and columns made up entirely of the given tile, the inverse of add_border
+    // Also returns the position in this map of the trimmed map's top-left corner, a map entirely of the tile trims to empty
+    pub fn trim_border(&self, tile: Tile) -> (Map, IVec2) {
+        self.trim_border_rings(tile, usize::MAX)
+    }
+
+    // Like trim_border but removing at most rings rows and columns from each side
+    // A map that already had walls around it comes back unchanged from add_border when trimmed by one ring
+    pub fn trim_border_rings(&self, tile: Tile, rings: usize) -> (Map, IVec2) {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        let row_is_border =
+            |y: &i32| (0..width).all(|x| self.get_tile(IVec2::new(x, *y)) == Some(&tile));
+        let top = (0..height).take_while(row_is_border).take(rings).count() as i32;
+        let bottom = (top..height)
+            .rev()
+            .take_while(row_is_border)
+            .take(rings)
+            .count() as i32;
+
+        let rows = top..height - bottom;
+        let column_is_border = |x: &i32| {
+            rows.clone()
+                .all(|y| self.get_tile(IVec2::new(*x, y)) == Some(&tile))
+        };
+        let left = (0..width).take_while(column_is_border).take(rings).count() as i32;
+        let right = (left..width)
+            .rev()
+            .take_while(column_is_border)
+            .take(rings)
+            .count() as i32;
+
+        let offset = IVec2::new(left, top);
+        let mut map = self.clone();
+        map.resize_with_offset(
+            (width - left - right) as usize,
+            (height - top - bottom) as usize,
+            -offset,
+            tile,
+        );
+        (map, offset)
+    }
+
+    // Moves every tile, portal and one-way marker by offset into a map of the new size, dropping any that fall off it
+    fn resize_with_offset(
+        &mut self,
+        new_width: usize,
+        new_height: usize,
+        offset: IVec2,
+        fill: Tile,
+    ) {
+        let mut resized = Map::from_fn(new_width, new_height, |pos| {
+            self.get_layer_tile(Layer::Terrain, pos - offset)
+                .copied()
+                .unwrap_or(fill)
+        });
+        let in_bounds = |pos: IVec2| resized.has_tile(pos + offset).then_some(pos + offset);
+        let objects = self
+            .objects
+            .iter()
+            .filter_map(|(pos, tile)| Some((in_bounds(*pos)?, *tile)))
+            .collect();
+        let portals = self
+            .portals
+            .iter()
+            .filter_map(|(entrance, exit)| Some((in_bounds(*entrance)?, in_bounds(*exit)?)))
+            .collect();
+        let one_way = self
+            .one_way
+            .iter()
+            .filter_map(|(pos, direction)| Some((in_bounds(*pos)?, *direction)))
+            .collect();
+        resized.objects = objects;
+        resized.portals = portals;
+        resized.one_way = one_way;
+        resized.corner_cutting = self.corner_cutting;
+        resized.wrap_mode = self.wrap_mode;
+        self.flush_pending_mutations();
+        resized.mutation_log = self.mutation_log.take();
+        *self = resized;
+    }
+
+    // Mirrors the map left to right
+    pub fn flip_horizontal(&self) -> Map {
+        let width = self.width() as i32;
+        self.transform(|pos| IVec2::new(width - 1 - pos.x, pos.y))
+    }
+
+    // Mirrors the map top to bottom
+    pub fn flip_vertical(&self) -> Map {
+        let height = self.height() as i32;
+        self.transform(|pos| IVec2::new(pos.x, height - 1 - pos.y))
+    }
+
+    pub fn rotate_180(&self) -> Map {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        self.transform(|pos| IVec2::new(width - 1 - pos.x, height - 1 - pos.y))
+    }
+
+    // Whether every cell on the outer edge of the map is impassable
+    pub fn border_is_sealed(&self) -> bool {
+        let (width, height) = (self.width() as i32, self.height() as i32);
+        self.get_tile_iterator()
+            .filter(|(pos, _)| {
+                pos.x == 0 || pos.y == 0 || pos.x == width - 1 || pos.y == height - 1
+            })
+            .all(|(_, tile)| *tile == Tile::IMPASSABLE)
+    }
+
+    // Checks every rule, returning all violations in the order of the rules
+    pub fn validate(&self, rules: &[ValidationRule]) -> Result<(), Vec<ValidationError>> {
+        let violations: Vec<ValidationError> = rules
+            .iter()
+            .filter_map(|rule| match rule {
+                ValidationRule::ExactlyOneTarget => {
+                    let targets = self.get_all_of_type(Tile::TARGET).len();
+                    (targets != 1).then_some(ValidationError::TargetCount(targets))
+                }
+                ValidationRule::SealedBorder => {
+                    (!self.border_is_sealed()).then_some(ValidationError::OpenBorder)
+                }
+                ValidationRule::AtLeastOnePassable => {
+                    let passable = self.get_tile_iterator().any(|(_, tile)| tile.is_passable());
+                    (!passable).then_some(ValidationError::NoPassableTiles)
+                }
+            })
+            .collect();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    pub fn is_symmetric(&self, axis: Symmetry) -> bool {
+        let transformed = match axis {
+            Symmetry::Horizontal => self.flip_horizontal(),
+            Symmetry::Vertical => self.flip_vertical(),
+            Symmetry::Rotational180 => self.rotate_180(),
+        };
+        *self == transformed
+    }
+
+    // Moves every tile and portal to the position given by a same-size remapping
+    fn transform(&self, remap: impl Fn(IVec2) -> IVec2) -> Map {
+        let mut map = self.clone();
+        for (y, row) in self.tiles.iter().enumerate() {
+            for (x, tile) in row.iter().enumerate() {
+                map.set_terrain_tile(remap(IVec2::new(x as i32, y as i32)), *tile);
+            }
+        }
+        map.objects = self
+            .objects
+            .iter()
+            .map(|(pos, tile)| (remap(*pos), *tile))
+            .collect();
+        map.portals = self
+            .portals
+            .iter()
+            .map(|(entrance, exit)| (remap(*entrance), remap(*exit)))
+            .collect();
+        // Directions are remapped through the offset they point along
+        map.one_way = self
+            .one_way
+            .iter()
+            .filter_map(|(pos, direction)| {
+                let offset = remap(pos + direction.to_ivec2()) - remap(*pos);
+                Direction::all8()
+                    .into_iter()
+                    .find(|candidate| candidate.to_ivec2() == offset)
+                    .map(|direction| (remap(*pos), direction))
+            })
+            .collect();
+        map
+    }
+
+    // Walks a Bresenham line from one position to another, returning every tile crossed in order
+    // The walk stops early if the line leaves the map
+    pub fn trace_line(&self, from: IVec2, to: IVec2) -> Vec<(IVec2, &Tile)> {
+        let delta = (to - from).abs();
+        let step = (to - from).signum();
+        let mut error = delta.x - delta.y;
+        let mut current = from;
+        let mut line = Vec::new();
+
+        while let Some(tile) = self.get_tile(current) {
+            line.push((current, tile));
+            if current == to {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled > -delta.y {
+                error -= delta.y;
+                current.x += step.x;
+            }
+            if doubled < delta.x {
+                error += delta.x;
+                current.y += step.y;
+            }
+        }
+        line
+    }
+
+    // One cellular automaton step, every cell becomes the rule applied to its tile and its Moore neighborhood
+    // Neighbors are in the order of Direction::all8, None where the neighbor is off the map
+    // Edges of the map follow the wrap mode as in get_neighbors8, so a wrapped map has no None neighbors
+    pub fn apply_rule<F: Fn(&Tile, &[Option<&Tile>; 8]) -> Tile>(&self, rule: F) -> Map {
+        let mut map = self.clone();
+        for (pos, tile) in self.get_tile_iterator() {
+            let neighborhood = Direction::all8().map(|direction| {
+                self.step(pos, direction, self.wrap_mode)
+                    .filter(|neighbor| *neighbor != pos)
+                    .and_then(|neighbor| self.get_tile(neighbor))
+            });
+            map.set_tile(pos, rule(tile, &neighborhood));
+        }
+        map
+    }
+
+    // One morphological dilation step, a cell becomes fill if any of its 4 neighbors is target
+    pub fn dilate(&self, target: Tile, fill: Tile) -> Map {
+        let mut map = self.clone();
+        for (pos, _tile) in self.get_tile_iterator() {
+            if self.neighbors_iter(pos).any(|(_, _, tile)| *tile == target) {
+                map.set_tile(pos, fill);
+            }
+        }
+        map
+    }
+
+    // One morphological erosion step, a target cell becomes fill unless all of its 4 neighbors are target
+    // Neighbors off the map are ignored
+    pub fn erode(&self, target: Tile, fill: Tile) -> Map {
+        let mut map = self.clone();
+        for (pos, tile) in self.get_tile_iterator() {
+            if *tile == target && !self.neighbors_iter(pos).all(|(_, _, tile)| *tile == target) {
+                map.set_tile(pos, fill);
+            }
+        }
+        map
+    }
+
+    // Renders the map like Display, optionally with column indices along the top and row indices down the side
+    // Multi-digit column indices are written vertically, one digit per header line
+    pub fn to_ascii_art(&self, with_coords: bool) -> String {
+        let body = self.to_string();
+        if !with_coords {
+            return body;
+        }
+
+        let gutter = self.height().saturating_sub(1).to_string().len();
+        let digits = self.width().saturating_sub(1).to_string().len();
+        let mut output = String::new();
+        for place in (0..digits).rev() {
+            output.push_str(&" ".repeat(gutter + 1));
+            for x in 0..self.width() {
+                let label = x.to_string();
+                // Leading places of shorter numbers are left blank
+                match label.len().checked_sub(place + 1) {
+                    Some(index) => output.push_str(&label[index..=index]),
+                    None => output.push(' '),
+                }
+            }
+            output.push('\n');
+        }
+        for (y, line) in body.lines().enumerate() {
+            output.push_str(&format!("{:>gutter$} {}\n", y, line));
+        }
+        output
+    }
+
+    // Renders the map as an image with each tile drawn as a tile_size square of its tile's color
+    #[cfg(feature = "image")]
+    pub fn to_image(&self, tile_size: u32) -> image::RgbaImage {
+        image::RgbaImage::from_fn(
+            self.width() as u32 * tile_size,
+            self.height() as u32 * tile_size,
+            |x, y| {
+                let pos = IVec2::new((x / tile_size) as i32, (y / tile_size) as i32);
+                image::Rgba(match self.get_tile(pos) {
+                    Some(Tile::CLEAN) | None => [240, 240, 240, 255],
+                    Some(Tile::DIRTY) => [139, 101, 60, 255],
+                    Some(Tile::IMPASSABLE) => [40, 40, 40, 255],
+                    Some(Tile::TARGET) => [220, 50, 50, 255],
+                    Some(Tile::KEY) => [230, 190, 40, 255],
+                    Some(Tile::DOOR) => [90, 60, 150, 255],
+                })
+            },
+        )
+    }
+
+    // Number of cells whose tiles differ between two maps of the same size
+    pub fn hamming_distance(&self, other: &Map) -> Result<usize, MapError> {
+        if self.dimensions() != other.dimensions() {
+            return Err(MapError::DimensionMismatch {
+                left: self.dimensions(),
+                right: other.dimensions(),
+            });
+        }
+        Ok(self
+            .get_tile_iterator()
+            .zip(other.get_tile_iterator())
+            .filter(|((_, left), (_, right))| left != right)
+            .count())
+    }
+
+    // Renders two maps side by side with differing cells shown in lowercase
+    // Maps with different dimensions are reported instead of compared
+    pub fn pretty_diff(&self, other: &Map) -> String {
+        if self.width() != other.width() || self.height() != other.height() {
+            return format!(
+                "Maps differ in size: {}x{} vs {}x{}\n",
+                self.width(),
+                self.height(),
+                other.width(),
+                other.height()
+            );
+        }
+
+        let mut output = String::new();
+        for (left, right) in self.to_string().lines().zip(other.to_string().lines()) {
+            let (mut left_row, mut right_row) = (String::new(), String::new());
+            for (a, b) in left.chars().zip(right.chars()) {
+                if a == b {
+                    left_row.push(a);
+                    right_row.push(b);
+                } else {
+                    left_row.push(a.to_ascii_lowercase());
+                    right_row.push(b.to_ascii_lowercase());
+                }
+            }
+            output.push_str(&format!("{} | {}\n", left_row, right_row));
+        }
+        output
+    }
+
+    // Whether pattern matches the subgrid of this map with its top-left corner at origin
+    // Pattern tiles equal to the wildcard match anything
+    pub fn subgrid_equal(&self, origin: IVec2, pattern: &Map, wildcard: Option<Tile>) -> bool {
+        pattern.get_tile_iterator().all(|(offset, pattern_tile)| {
+            Some(*pattern_tile) == wildcard || self.get_tile(origin + offset) == Some(pattern_tile)
+        })
+    }
+
+    // Every top-left position where pattern matches a subgrid of this map, in row-major order
+    pub fn find_pattern(&self, pattern: &Map, wildcard: Option<Tile>) -> Vec<IVec2> {
+        if pattern.width() > self.width() || pattern.height() > self.height() {
+            return Vec::new();
+        }
+        let mut matches = Vec::new();
+        for y in 0..=(self.height() - pattern.height()) as i32 {
+            for x in 0..=(self.width() - pattern.width()) as i32 {
+                let origin = IVec2::new(x, y);
+                if self.subgrid_equal(origin, pattern, wildcard) {
+                    matches.push(origin);
+                }
+            }
+        }
+        matches
+    }
+
+    // Steps from origin in a direction until hitting an impassable tile, returning its position
+    // Returns None if the ray leaves the map or travels max_dist tiles without a hit
+    pub fn raycast(&self, origin: IVec2, dir: Direction, max_dist: i32) -> Option<IVec2> {
+        (1..=max_dist)
+            .map(|distance| origin + dir.to_ivec2() * distance)
+            .map_while(|pos| self.get_tile(pos).map(|tile| (pos, tile)))
+            .find(|(_, tile)| **tile == Tile::IMPASSABLE)
+            .map(|(pos, _)| pos)
+    }
+
+    // Every move between adjacent passable tiles along with the movement cost of the tile entered
+    // Edges are directed, so each adjacency appears once for each direction a one-way tile allows crossing it
+    // Moves across the edge of a wrapped map are included
+    pub fn edges(&self) -> Vec<(IVec2, IVec2, i32)> {
+        self.edges_with(Tile::movement_cost)
+    }
+
+    // Every move a search under the cost model can make, with positions replaced by their row-major indices for external
+    // solvers, so portals, wrapping and one-way tiles are all included
+    // Tiles the model makes impassable have no edges in or out
+    pub fn to_adjacency_list(&self, cost: &CostModel) -> Vec<(usize, usize, i32)> {
+        let graph = self.with_cost_model(cost);
+        let mut edges = Vec::new();
+        for (pos, tile) in self.get_tile_iterator() {
+            if cost.cost(tile).is_none() {
+                continue;
+            }
+            for (neighbor, cost) in graph.neighbors(pos) {
+                if let (Some(from), Some(to)) = (self.to_index(pos), self.to_index(neighbor)) {
+                    edges.push((from, to, cost));
+                }
+            }
+        }
+        edges
+    }
+
+    // Edges leaving every tile that can be entered, costed by the cost of the tile entered
+    fn edges_with(&self, cost: impl Fn(&Tile) -> Option<i32>) -> Vec<(IVec2, IVec2, i32)> {
+        let mut edges = Vec::new();
+        for (pos, tile) in self.get_tile_iterator() {
+            if cost(tile).is_none() {
+                continue;
+            }
+            for (neighbor, _direction, tile) in self.moves_iter(pos) {
+                if let Some(cost) = cost(tile) {
+                    edges.push((pos, neighbor, cost));
+                }
+            }
+        }
+        edges
+    }
+
+    // Graphviz digraph of edges(), labelled with movement costs
+    pub fn to_dot(&self) -> String {
+        let mut output = String::from("digraph map {\n");
+        for (from, to, cost) in self.edges() {
+            output.push_str(&format!(
+                "    \"{},{}\" -> \"{},{}\" [label={}];\n",
+                from.x, from.y, to.x, to.y, cost
+            ));
+        }
+        output.push_str("}\n");
+        output
+    }
+}
+
+// Leading bytes that keep hashes of objects, portals, one-way tiles and settings apart from terrain cells
+const OBJECT_TAG: u8 = 1;
+const PORTAL_TAG: u8 = 2;
+const ONE_WAY_TAG: u8 = 3;
+const SETTINGS_TAG: u8 = 4;
+
+// FNV-1a hash of a sequence of bytes
+fn fnv_hash(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn pos_bytes(pos: IVec2) -> impl Iterator<Item = u8> {
+    pos.x.to_le_bytes().into_iter().chain(pos.y.to_le_bytes())
+}
+
+// Hash of something at a position other than a terrain cell, the tag says what it is
+fn tagged_hash(tag: u8, pos: IVec2, rest: impl IntoIterator<Item = u8>) -> u64 {
+    fnv_hash([tag].into_iter().chain(pos_bytes(pos)).chain(rest))
+}
+
+// Hash of a single terrain cell, combined across the map with XOR so cells can be swapped in and out
+fn cell_hash(pos: IVec2, tile: &Tile) -> u64 {
+    fnv_hash(pos_bytes(pos).chain([*tile as u8]))
+}
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        // Differing checksums rule maps out without comparing every tile
+        self.checksum() == other.checksum()
+            && self.tiles == other.tiles
+            && self.objects == other.objects
+            && self.portals == other.portals
+            && self.one_way == other.one_way
+            && self.corner_cutting == other.corner_cutting
+            && self.wrap_mode == other.wrap_mode
+    }
+}
+
+impl Eq for Map {}
+
+// Rows of tiles indexed [y][x], rows are not checked to be the same length
+impl From<Vec<Vec<Tile>>> for Map {
+    fn from(tiles: Vec<Vec<Tile>>) -> Self {
+        Self::from_tiles(tiles)
+    }
+}
+
+// Rows of the tiles returned by get_tile, flattening the object layer onto the terrain
+impl From<Map> for Vec<Vec<Tile>> {
+    fn from(map: Map) -> Self {
+        map.get_line_iterator()
+            .map(|line| line.into_iter().map(|(_, tile)| *tile).collect())
+            .collect()
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render_with(&RenderOptions::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::Metric;
+
+    fn load(text: &str) -> Map {
+        Map::load_from_reader(text.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn neighbors_iter_matches_get_neighbors() {
+        let map = load("CCDCC\nCWWWC\nCCTCC\n");
+        for (pos, _tile) in map.get_tile_iterator() {
+            let iterated: Vec<(IVec2, Direction, &Tile)> = map.neighbors_iter(pos).collect();
+            let expected = map.get_neighbors(&pos);
+            assert_eq!(iterated.len(), expected.len(), "neighbors of {}", pos);
+            for (neighbor, direction, tile) in &iterated {
+                assert_eq!(expected.get(neighbor), Some(&(*direction, *tile)));
+            }
+            // Yielded in the order of Direction::all()
+            let order: Vec<usize> = iterated
+                .iter()
+                .map(|(_, direction, _)| {
+                    Direction::all()
+                        .iter()
+                        .position(|candidate| candidate == direction)
+                        .unwrap()
+                })
+                .collect();
+            assert!(order.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
+    #[test]
+    fn wrapped_shortest_path_crosses_the_edge() {
+        let mut map = load("CCCCCCCC\nCCCCCCCC\n");
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(7, 0));
+        assert_eq!(
+            astar(&map, start, goal, None),
+            SearchOutcome::Found((0..8).map(|x| IVec2::new(x, 0)).collect())
+        );
+
+        map.set_wrap_mode(WrapMode::Wrap);
+        assert_eq!(
+            astar(&map, start, goal, None),
+            SearchOutcome::Found(vec![start, goal])
+        );
+        // The heuristic measures the short way around, so it stays admissible across the seam
+        assert_eq!(map.heuristic(Metric::Manhattan, start, goal), 1);
+        assert_eq!(
+            map.heuristic(Metric::Manhattan, IVec2::new(1, 0), IVec2::new(6, 1)),
+            4
+        );
+        assert!(map.edges().contains(&(goal, start, 1)));
+        let neighbors8 = map.get_neighbors8(&start);
+        assert_eq!(
+            neighbors8.get(&IVec2::new(7, 1)),
+            Some(&(Direction::DownLeft, &Tile::CLEAN))
+        );
+    }
+
+    #[test]
+    fn clamped_edges_are_not_neighbors() {
+        let mut map = load("CCC\nCCC\n");
+        map.set_wrap_mode(WrapMode::Clamp);
+        let corner = IVec2::new(0, 0);
+        assert!(map.neighbors_iter(corner).all(|(pos, _, _)| pos != corner));
+        assert_eq!(map.get_neighbors(&corner).len(), 2);
+        assert!(!map.get_neighbors8(&corner).contains_key(&corner));
+        assert!(map.edges().iter().all(|(from, to, _)| from != to));
+    }
+
+    #[test]
+    fn equal_maps_share_a_checksum() {
+        let (mut a, b) = (load("CCD\nCWT\n"), load("CCD\nCWT\n"));
+        assert_eq!(a.checksum(), b.checksum());
+
+        a.set_tile(IVec2::new(0, 0), Tile::DIRTY);
+        assert_ne!(a.checksum(), b.checksum());
+        a.set_tile(IVec2::new(0, 0), Tile::CLEAN);
+        assert_eq!(a.checksum(), b.checksum());
+
+        *a.get_tile_mut(IVec2::new(1, 0)) = Tile::IMPASSABLE;
+        assert_ne!(a.checksum(), b.checksum());
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn checksum_covers_objects_and_routing() {
+        let base = load("CCC\nCCC\n");
+        let edits: [fn(&mut Map); 5] = [
+            |map| map.set_layer_tile(Layer::Objects, IVec2::new(1, 0), Tile::KEY),
+            |map| map.add_portal(IVec2::new(0, 0), IVec2::new(2, 1), false),
+            |map| map.set_one_way(IVec2::new(1, 1), Direction::Left),
+            |map| map.set_wrap_mode(WrapMode::Wrap),
+            |map| map.set_corner_cutting(CornerCutting::Allow),
+        ];
+        for edit in edits {
+            let mut map = base.clone();
+            edit(&mut map);
+            assert_ne!(map.checksum(), base.checksum());
+        }
+
+        // An object matching the terrain beneath it still changes the checksum
+        let mut map = base.clone();
+        map.set_layer_tile(Layer::Objects, IVec2::new(0, 0), Tile::CLEAN);
+        assert_ne!(map.checksum(), base.checksum());
+        map.clear_layer_tile(Layer::Objects, IVec2::new(0, 0));
+        assert_eq!(map.checksum(), base.checksum());
+    }
+
+    #[test]
+    fn one_way_shortcut_forces_a_detour() {
+        let mut map = load("CCCCC\nCWWWC\nCCCCC\n");
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(4, 0));
+        map.set_one_way(IVec2::new(2, 0), Direction::Down);
+        let SearchOutcome::Found(path) = astar(&map, start, goal, None) else {
+            panic!("no path around the one-way tile");
+        };
+        assert_eq!(path.len(), 9);
+        assert!(!path.contains(&IVec2::new(2, 0)));
+
+        // The grid's shape is unchanged, only moves honor the one-way tile
+        let one_way = IVec2::new(2, 0);
+        assert_eq!(map.neighbors_iter(one_way).count(), 3);
+        assert_eq!(map.moves_iter(one_way).count(), 1);
+        assert_eq!(map.label_regions().values().max(), Some(&0));
+        assert!(!map
+            .edges()
+            .iter()
+            .any(|(from, to, _)| *from == one_way && *to == IVec2::new(3, 0)));
+        // Its only way out is into the wall
+        assert_eq!(map.available_actions(one_way), [Action::Wait]);
+    }
+
+    #[test]
+    fn object_layer_target_shows_above_terrain() {
+        let mut map = load("CC\nCC\n");
+        let pos = IVec2::new(1, 0);
+        map.set_layer_tile(Layer::Objects, pos, Tile::TARGET);
+        assert_eq!(map.get_tile(pos), Some(&Tile::TARGET));
+        assert_eq!(map.get_layer_tile(Layer::Terrain, pos), Some(&Tile::CLEAN));
+        assert_eq!(map.targets(), [pos]);
+        map.clear_layer_tile(Layer::Objects, pos);
+        assert_eq!(map.get_tile(pos), Some(&Tile::CLEAN));
+    }
+
+    #[test]
+    fn mutators_write_the_visible_layer() {
+        let mut map = load("CCC\nCCC\n");
+        let key = IVec2::new(1, 0);
+        map.set_layer_tile(Layer::Objects, key, Tile::KEY);
+
+        map.set_tile(key, Tile::DIRTY);
+        assert_eq!(map.get_layer_tile(Layer::Objects, key), Some(&Tile::DIRTY));
+        assert_eq!(map.get_layer_tile(Layer::Terrain, key), Some(&Tile::CLEAN));
+
+        *map.get_tile_mut(key) = Tile::IMPASSABLE;
+        assert_eq!(map.get_tile(key), Some(&Tile::IMPASSABLE));
+        assert_eq!(map.get_layer_tile(Layer::Terrain, key), Some(&Tile::CLEAN));
+
+        assert_eq!(map.replace_tiles(Tile::IMPASSABLE, Tile::DIRTY), 1);
+        assert_eq!(map.get_layer_tile(Layer::Objects, key), Some(&Tile::DIRTY));
+
+        // Dilating spreads from the object to the terrain around it, eroding overwrites the lone object in place
+        let dilated = map.dilate(Tile::DIRTY, Tile::DIRTY);
+        assert_eq!(dilated.get_all_of_type(Tile::DIRTY).len(), 4);
+        let eroded = map.erode(Tile::DIRTY, Tile::CLEAN);
+        assert_eq!(
+            eroded.get_layer_tile(Layer::Objects, key),
+            Some(&Tile::CLEAN)
+        );
+        assert_eq!(
+            eroded.get_layer_tile(Layer::Terrain, key),
+            Some(&Tile::CLEAN)
+        );
+    }
+
+    #[test]
+    fn mutation_log_records_each_set_tile() {
+        let mut map = load("CCC\nCCC\n");
+        map.set_mutation_logging(true);
+        map.set_tile(IVec2::new(0, 0), Tile::DIRTY);
+        map.set_tile(IVec2::new(1, 1), Tile::IMPASSABLE);
+        map.set_tile(IVec2::new(0, 0), Tile::TARGET);
+        // Setting a tile to what it already is changes nothing
+        map.set_tile(IVec2::new(2, 0), Tile::CLEAN);
+        assert_eq!(
+            map.drain_mutations(),
+            [
+                TileMutation {
+                    pos: IVec2::new(0, 0),
+                    old: Tile::CLEAN,
+                    new: Tile::DIRTY
+                },
+                TileMutation {
+                    pos: IVec2::new(1, 1),
+                    old: Tile::CLEAN,
+                    new: Tile::IMPASSABLE
+                },
+                TileMutation {
+                    pos: IVec2::new(0, 0),
+                    old: Tile::DIRTY,
+                    new: Tile::TARGET
+                },
+            ]
+        );
+        assert!(map.mutation_log().unwrap().is_empty());
+    }
+
+    #[test]
+    fn mutation_log_records_objects_and_direct_writes() {
+        let mut map = load("CC\nCC\n");
+        let (key, corner) = (IVec2::new(1, 0), IVec2::new(0, 1));
+        map.set_mutation_logging(true);
+        map.set_layer_tile(Layer::Objects, key, Tile::KEY);
+        // Terrain hidden beneath the key does not change what is shown
+        map.set_layer_tile(Layer::Terrain, key, Tile::DIRTY);
+        map.clear_layer_tile(Layer::Objects, key);
+        *map.get_tile_mut(corner) = Tile::DIRTY;
+        map.set_tile(corner, Tile::IMPASSABLE);
+        map.set_tile(IVec2::new(0, 0), Tile::DIRTY);
+        assert_eq!(
+            map.drain_mutations(),
+            [
+                TileMutation {
+                    pos: key,
+                    old: Tile::CLEAN,
+                    new: Tile::KEY
+                },
+                TileMutation {
+                    pos: key,
+                    old: Tile::KEY,
+                    new: Tile::DIRTY
+                },
+                TileMutation {
+                    pos: corner,
+                    old: Tile::CLEAN,
+                    new: Tile::DIRTY
+                },
+                TileMutation {
+                    pos: corner,
+                    old: Tile::DIRTY,
+                    new: Tile::IMPASSABLE
+                },
+                TileMutation {
+                    pos: IVec2::new(0, 0),
+                    old: Tile::CLEAN,
+                    new: Tile::DIRTY
+                },
+            ]
+        );
+
+        // Resizing keeps logging and anything not yet drained
+        map.set_tile(corner, Tile::CLEAN);
+        map.resize(3, 3, Tile::CLEAN);
+        map.set_tile(IVec2::new(2, 2), Tile::TARGET);
+        assert_eq!(
+            map.drain_mutations(),
+            [
+                TileMutation {
+                    pos: corner,
+                    old: Tile::IMPASSABLE,
+                    new: Tile::CLEAN
+                },
+                TileMutation {
+                    pos: IVec2::new(2, 2),
+                    old: Tile::CLEAN,
+                    new: Tile::TARGET
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_maps_iterate_in_row_major_order() {
+        let map = Map::load_from_reader("CCW\nWCC\n".as_bytes()).unwrap();
+        let row_major = [
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(1, 1),
+            IVec2::new(2, 1),
+        ];
+        let labels: Vec<IVec2> = map.label_regions().into_keys().collect();
+        assert_eq!(labels, row_major);
+        let clearance: Vec<IVec2> = map.clearance_map().keys().collect();
+        let tiles: Vec<IVec2> = (0..2)
+            .flat_map(|y| (0..3).map(move |x| IVec2::new(x, y)))
+            .collect();
+        assert_eq!(clearance, tiles);
+
+        let distances = map.all_pairs_distances();
+        let sources: Vec<IVec2> = distances.keys().collect();
+        assert_eq!(sources, row_major);
+        let from_start: Vec<(IVec2, u32)> = distances
+            .get(&IVec2::new(0, 0))
+            .unwrap()
+            .iter()
+            .map(|(pos, distance)| (pos, *distance))
+            .collect();
+        assert_eq!(
+            from_start,
+            [
+                (IVec2::new(0, 0), 0),
+                (IVec2::new(1, 0), 1),
+                (IVec2::new(1, 1), 2),
+                (IVec2::new(2, 1), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn spaces_load_as_the_configured_tile() {
+        let options = LoadOptions::new()
+            .with_trim_whitespace(false)
+            .with_space_tile(Some(Tile::CLEAN));
+        let map = Map::load_from_reader_with(" W \r\nD  \r\n".as_bytes(), options).unwrap();
+        let tiles: Vec<Tile> = map.get_tile_iterator().map(|(_, tile)| *tile).collect();
+        assert_eq!(map.dimensions(), IVec2::new(3, 2));
+        assert_eq!(
+            tiles,
+            [
+                Tile::CLEAN,
+                Tile::IMPASSABLE,
+                Tile::CLEAN,
+                Tile::DIRTY,
+                Tile::CLEAN,
+                Tile::CLEAN
+            ]
+        );
+    }
+
+    #[test]
+    fn spaces_are_unknown_tiles_by_default() {
+        assert!(matches!(
+            Map::load_from_reader(" C C \r\n".as_bytes()),
+            Err(MapError::UnknownTile {
+                character: ' ',
+                position
+            }) if position == IVec2::new(1, 0)
+        ));
+    }
+
+    #[test]
+    fn file_loader_takes_options() {
+        let path = std::env::temp_dir().join("csc411_file_loader_takes_options.txt");
+        std::fs::write(&path, "C W\r\nWTC\r\n").unwrap();
+        let options = LoadOptions::new()
+            .with_trim_whitespace(false)
+            .with_space_tile(Some(Tile::DIRTY));
+        let map = Map::load_from_file_with(path.to_str().unwrap(), options).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(map.get_tile(IVec2::new(1, 0)), Some(&Tile::DIRTY));
+        assert_eq!(map.get_tile(IVec2::new(1, 1)), Some(&Tile::TARGET));
+    }
+
+    #[test]
+    fn expensive_dirt_reroutes_the_path() {
+        let map = Map::load_from_reader("CDC\nCCC\n".as_bytes()).unwrap();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 0));
+        let straight = vec![start, IVec2::new(1, 0), goal];
+        assert_eq!(
+            astar(&map, start, goal, None),
+            SearchOutcome::Found(straight)
+        );
+
+        let model = CostModel::new().with_cost(Tile::DIRTY, Some(10));
+        let SearchOutcome::Found(path) = astar(&map.with_cost_model(&model), start, goal, None)
+        else {
+            panic!("expected a path around the dirt");
+        };
+        assert_eq!(path.len(), 5);
+        assert!(!path.contains(&IVec2::new(1, 0)));
+        assert_eq!(model.cost(&Tile::CLEAN), Tile::CLEAN.movement_cost());
+    }
+
+    #[test]
+    #[should_panic(expected = "tile costs must be at least 1")]
+    fn zero_costs_are_rejected() {
+        CostModel::new().set_cost(Tile::CLEAN, Some(0));
+    }
+
+    #[test]
+    fn adjacency_list_matches_edges_under_the_cost_model() {
+        let map = Map::load_from_reader("CDW\nCCD\n".as_bytes()).unwrap();
+        let model = CostModel::new().with_cost(Tile::DIRTY, Some(5));
+        let mut expected: Vec<(usize, usize, i32)> = map
+            .edges()
+            .into_iter()
+            .map(|(from, to, _)| {
+                let cost = model.cost(map.get_tile(to).unwrap()).unwrap();
+                (map.to_index(from).unwrap(), map.to_index(to).unwrap(), cost)
+            })
+            .collect();
+        let mut edges = map.to_adjacency_list(&model);
+        expected.sort();
+        edges.sort();
+        assert_eq!(edges, expected);
+
+        let no_dirt = CostModel::new().with_cost(Tile::DIRTY, None);
+        let dirt = map.to_index(IVec2::new(1, 0)).unwrap();
+        assert!(map
+            .to_adjacency_list(&no_dirt)
+            .iter()
+            .all(|(from, to, _)| *from != dirt && *to != dirt));
+    }
+
+    #[test]
+    fn adjacency_list_follows_portals_and_one_way_tiles() {
+        let mut map = Map::new(4, 1);
+        map.add_portal(IVec2::new(0, 0), IVec2::new(3, 0), false);
+        map.set_one_way(IVec2::new(1, 0), Direction::Right);
+        let edges = map.to_adjacency_list(&CostModel::new());
+        assert!(edges.contains(&(0, 3, 1)));
+        assert!(!edges.contains(&(3, 0, 1)));
+        assert!(edges.contains(&(1, 2, 1)));
+        assert!(!edges.contains(&(1, 0, 1)));
+        assert!(edges.contains(&(0, 1, 1)));
+    }
+
+    #[test]
+    fn trimming_undoes_add_border() {
+        let map = load("CWDC\nTCCW\nWCDC\n");
+        let bordered = map.add_border(Tile::IMPASSABLE);
+        assert_eq!(bordered.dimensions(), IVec2::new(6, 5));
+
+        let (trimmed, offset) = bordered.trim_border(Tile::IMPASSABLE);
+        assert_eq!(offset, IVec2::ONE);
+        assert_eq!(trimmed.to_string(), map.to_string());
+        assert_eq!(trimmed.checksum(), map.checksum());
+    }
+
+    #[test]
+    fn trimming_rings_keeps_inner_walls() {
+        let map = load("WWWW\nWCDW\nWTCW\nWWWW\n");
+        let bordered = map.add_border(Tile::IMPASSABLE);
+
+        let (trimmed, offset) = bordered.trim_border_rings(Tile::IMPASSABLE, 1);
+        assert_eq!(offset, IVec2::ONE);
+        assert_eq!(trimmed.to_string(), map.to_string());
+
+        let (inner, offset) = bordered.trim_border(Tile::IMPASSABLE);
+        assert_eq!(offset, IVec2::new(2, 2));
+        assert_eq!(inner.to_string(), load("CD\nTC\n").to_string());
+    }
+
+    #[test]
+    fn symmetric_maps_are_recognised() {
+        let mirrored = load("WCCW\nDCCD\nWTTW\n");
+        assert!(mirrored.is_symmetric(Symmetry::Horizontal));
+        assert!(!mirrored.is_symmetric(Symmetry::Vertical));
+        assert!(!mirrored.is_symmetric(Symmetry::Rotational180));
+
+        let rotated = load("WCD\nCTC\nDCW\n");
+        assert!(rotated.is_symmetric(Symmetry::Rotational180));
+        assert!(!rotated.is_symmetric(Symmetry::Horizontal));
+
+        let stacked = load("CDC\nWWW\nCDC\n");
+        assert!(stacked.is_symmetric(Symmetry::Vertical));
+        assert!(stacked.is_symmetric(Symmetry::Horizontal));
+        assert!(stacked.is_symmetric(Symmetry::Rotational180));
+    }
+
+    #[test]
+    fn asymmetric_maps_are_rejected() {
+        let map = load("CCW\nDCC\nCCT\n");
+        for axis in [
+            Symmetry::Horizontal,
+            Symmetry::Vertical,
+            Symmetry::Rotational180,
+        ] {
+            assert!(!map.is_symmetric(axis));
+        }
+    }
+
+    #[test]
+    fn indices_round_trip_in_bounds() {
+        let map = Map::new(4, 3);
+        for (pos, _tile) in map.get_tile_iterator() {
+            let index = map.to_index(pos).unwrap();
+            assert!(index < 12);
+            assert_eq!(map.from_index(index), pos);
+        }
+        assert_eq!(map.to_index(IVec2::new(1, 2)), Some(9));
+        for outside in [
+            IVec2::new(-1, 0),
+            IVec2::new(0, -1),
+            IVec2::new(4, 0),
+            IVec2::new(0, 3),
+        ] {
+            assert_eq!(map.to_index(outside), None);
+        }
+    }
+
+    #[test]
+    fn corner_cutting_rules_prune_diagonals() {
+        let corner = IVec2::new(0, 0);
+        let diagonal = IVec2::new(1, 1);
+        let mut both_walls = load("CWC\nWCC\nCCC\n");
+        let mut one_wall = load("CWC\nCCC\nCCC\n");
+
+        let rules = [
+            (CornerCutting::Allow, true, true),
+            (CornerCutting::Disallow, false, false),
+            (CornerCutting::RequireOneClear, false, true),
+        ];
+        for (rule, past_both, past_one) in rules {
+            both_walls.set_corner_cutting(rule);
+            one_wall.set_corner_cutting(rule);
+            assert_eq!(
+                both_walls.get_neighbors8(&corner).contains_key(&diagonal),
+                past_both
+            );
+            assert_eq!(
+                one_wall.get_neighbors8(&corner).contains_key(&diagonal),
+                past_one
+            );
+        }
+    }
+
+    #[test]
+    fn trace_line_crosses_cells_in_order() {
+        let map = Map::new(5, 5);
+        let cells = |from, to| -> Vec<IVec2> {
+            map.trace_line(from, to)
+                .into_iter()
+                .map(|(pos, _tile)| pos)
+                .collect()
+        };
+        assert_eq!(
+            cells(IVec2::new(0, 0), IVec2::new(3, 3)),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(1, 1),
+                IVec2::new(2, 2),
+                IVec2::new(3, 3)
+            ]
+        );
+        assert_eq!(
+            cells(IVec2::new(0, 0), IVec2::new(4, 2)),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(1, 0),
+                IVec2::new(2, 1),
+                IVec2::new(3, 1),
+                IVec2::new(4, 2)
+            ]
+        );
+        assert_eq!(cells(IVec2::new(3, 3), IVec2::new(7, 7)).len(), 2);
+    }
+
+    #[test]
+    fn dilating_a_wall_makes_a_plus() {
+        let single = load("CCCCC\nCCCCC\nCCWCC\nCCCCC\nCCCCC\n");
+        let plus = load("CCCCC\nCCWCC\nCWWWC\nCCWCC\nCCCCC\n");
+        let dilated = single.dilate(Tile::IMPASSABLE, Tile::IMPASSABLE);
+        assert_eq!(dilated.to_string(), plus.to_string());
+        let eroded = dilated.erode(Tile::IMPASSABLE, Tile::CLEAN);
+        assert_eq!(eroded.to_string(), single.to_string());
+    }
+
+    #[test]
+    fn open_center_has_eight_passable_neighbors() {
+        let map = Map::new(3, 3);
+        let center = map.neighbors8_passable(&IVec2::new(1, 1));
+        assert_eq!(center.len(), 8);
+        assert!(center
+            .iter()
+            .all(|(pos, (direction, _))| pos == IVec2::new(1, 1) + direction.to_ivec2()));
+        assert_eq!(map.neighbors8_passable(&IVec2::new(0, 0)).len(), 3);
+        assert_eq!(map.get_neighbors(&IVec2::new(1, 1)).len(), 4);
+
+        let walled = load("CWC\nCCC\nCCC\n");
+        assert_eq!(walled.neighbors8_passable(&IVec2::new(1, 1)).len(), 5);
+    }
+
+    #[test]
+    fn replacing_targets_counts_each_one() {
+        let mut map = load("TCD\nWTC\nCCT\n");
+        let targets = map.get_all_of_type(Tile::TARGET).len();
+        assert_eq!(map.replace_tiles(Tile::TARGET, Tile::CLEAN), targets);
+        assert_eq!(targets, 3);
+        assert!(map.get_all_of_type(Tile::TARGET).is_empty());
+        assert_eq!(map.get_all_of_type(Tile::CLEAN).len(), 7);
+        assert_eq!(map.replace_tiles(Tile::TARGET, Tile::CLEAN), 0);
+    }
+
+    #[test]
+    fn from_fn_builds_a_checkerboard() {
+        let map = Map::from_fn(4, 3, |pos| {
+            if (pos.x + pos.y) % 2 == 0 {
+                Tile::CLEAN
+            } else {
+                Tile::DIRTY
+            }
+        });
+        assert_eq!(map.dimensions(), IVec2::new(4, 3));
+        assert_eq!(map.get_tile(IVec2::new(0, 0)), Some(&Tile::CLEAN));
+        assert_eq!(map.get_tile(IVec2::new(1, 0)), Some(&Tile::DIRTY));
+        assert_eq!(map.get_tile(IVec2::new(0, 1)), Some(&Tile::DIRTY));
+        assert_eq!(map.get_tile(IVec2::new(3, 2)), Some(&Tile::DIRTY));
+        assert_eq!(map.get_all_of_type(Tile::DIRTY).len(), 6);
+        assert_eq!(map.checksum(), load("CDCD\nDCDC\nCDCD\n").checksum());
+    }
+
+    #[test]
+    fn ascii_art_gutters_label_rows_and_columns() {
+        let map = Map::new(10, 10);
+        let art = map.to_ascii_art(true);
+        let lines: Vec<&str> = art.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "  0123456789");
+        assert_eq!(lines[1], "0 CCCCCCCCCC");
+        assert_eq!(lines[10], "9 CCCCCCCCCC");
+        assert_eq!(map.to_ascii_art(false), map.to_string());
+
+        let wide = Map::new(12, 11).to_ascii_art(true);
+        let lines: Vec<&str> = wide.lines().collect();
+        assert_eq!(lines[0], "             11");
+        assert_eq!(lines[1], "   012345678901");
+        assert_eq!(lines[2], " 0 CCCCCCCCCCCC");
+        assert_eq!(lines[12], "10 CCCCCCCCCCCC");
+    }
+
+    #[test]
+    fn corner_block_keeps_only_cells_on_the_map() {
+        let map = load("CDC\nWCC\nCCT\n");
+        let corner = map.get_block(&IVec2::new(0, 0));
+        assert_eq!(
+            corner.keys().collect::<Vec<IVec2>>(),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(1, 0),
+                IVec2::new(0, 1),
+                IVec2::new(1, 1)
+            ]
+        );
+        assert_eq!(corner.get(&IVec2::new(1, 0)), Some(&&Tile::DIRTY));
+        assert_eq!(map.get_block(&IVec2::new(1, 1)).len(), 9);
+        assert_eq!(map.get_block(&IVec2::new(2, 1)).len(), 6);
+    }
+
+    #[test]
+    fn caves_are_connected_and_reproducible() {
+        let cave = Map::generate_cave(40, 30, 0.45, 4, 11);
+        assert_eq!(cave.dimensions(), IVec2::new(40, 30));
+        let labels = cave.label_regions();
+        assert!(!labels.is_empty());
+        assert!(labels.values().all(|label| *label == 0));
+
+        let again = Map::generate_cave(40, 30, 0.45, 4, 11);
+        assert_eq!(again.to_string(), cave.to_string());
+        assert_ne!(
+            Map::generate_cave(40, 30, 0.45, 4, 12).to_string(),
+            cave.to_string()
+        );
+    }
+
+    #[test]
+    fn maps_parse_from_an_in_memory_cursor() {
+        let bytes: &[u8] = b"CDW\n\nKLT\n";
+        let map = Map::load_from_reader(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(map.dimensions(), IVec2::new(3, 2));
+        assert_eq!(map.get_tile(IVec2::new(1, 0)), Some(&Tile::DIRTY));
+        assert_eq!(map.get_tile(IVec2::new(1, 1)), Some(&Tile::DOOR));
+        assert!(matches!(
+            Map::load_from_reader(std::io::Cursor::new(b"  \n".as_slice())),
+            Err(MapError::Empty)
+        ));
+        assert!(matches!(
+            Map::load_from_reader(std::io::Cursor::new(b"CC\nCX\n".as_slice())),
+            Err(MapError::UnknownTile { character: 'X', position }) if position == IVec2::new(1, 1)
+        ));
+    }
+
+    #[test]
+    fn pretty_diff_lowercases_only_changed_cells() {
+        let before = load("CCW\nDCT\n");
+        let mut after = before.clone();
+        after.set_tile(IVec2::new(0, 1), Tile::CLEAN);
+        assert_eq!(before.pretty_diff(&after), "CCW | CCW\ndCT | cCT\n");
+        assert_eq!(before.pretty_diff(&before), "CCW | CCW\nDCT | DCT\n");
+        assert_eq!(
+            before.pretty_diff(&Map::new(2, 2)),
+            "Maps differ in size: 3x2 vs 2x2\n"
+        );
+    }
+
+    #[test]
+    fn stats_count_tiles_and_wall_ratio() {
+        let stats = load("WWWW\nWCDW\nWTCW\n").stats();
+        assert_eq!((stats.width, stats.height), (4, 3));
+        assert_eq!(stats.counts.get(&Tile::IMPASSABLE), Some(&8));
+        assert_eq!(stats.counts.get(&Tile::CLEAN), Some(&2));
+        assert_eq!(stats.counts.get(&Tile::DIRTY), Some(&1));
+        assert_eq!(stats.counts.get(&Tile::TARGET), Some(&1));
+        assert_eq!(stats.counts.get(&Tile::KEY), None);
+        assert!((stats.wall_ratio - 8.0 / 12.0).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn neighbors_sort_toward_the_target() {
+        let map = Map::new(5, 5);
+        let center = IVec2::new(2, 2);
+        let sorted = map.neighbors_sorted_toward(&center, IVec2::new(4, 2));
+        let directions: Vec<Direction> =
+            sorted.iter().map(|(_, direction, _)| *direction).collect();
+        assert_eq!(directions.len(), 4);
+        assert_eq!(directions[0], Direction::Right);
+        assert_eq!(directions[3], Direction::Left);
+
+        let target = IVec2::new(0, 4);
+        let distances: Vec<i32> = map
+            .neighbors_sorted_toward(&center, target)
+            .iter()
+            .map(|(pos, _, _)| manhattan_distance(pos, &target))
+            .collect();
+        assert_eq!(distances, [3, 3, 5, 5]);
+    }
+
+    #[test]
+    fn patterns_are_found_at_every_match() {
+        let map = load("CTTCC\nCTTCC\nCCCTT\nWCCTT\nTTCCC\n");
+        let pattern = load("TT\nTT\n");
+        assert_eq!(
+            map.find_pattern(&pattern, None),
+            [IVec2::new(1, 0), IVec2::new(3, 2)]
+        );
+        assert!(map.subgrid_equal(IVec2::new(3, 2), &pattern, None));
+        assert!(!map.subgrid_equal(IVec2::new(0, 4), &pattern, None));
+
+        // Wildcard tiles in the pattern match anything
+        let corner = load("TK\nTK\n");
+        assert_eq!(
+            map.find_pattern(&corner, Some(Tile::KEY)),
+            [IVec2::new(1, 0), IVec2::new(2, 0), IVec2::new(3, 2)]
+        );
+        assert!(map.find_pattern(&Map::new(6, 1), None).is_empty());
+    }
+
+    #[test]
+    fn raycast_hits_the_first_wall() {
+        let map = load("CCCWCW\n");
+        let origin = IVec2::new(0, 0);
+        assert_eq!(
+            map.raycast(origin, Direction::Right, 10),
+            Some(IVec2::new(3, 0))
+        );
+        assert_eq!(map.raycast(origin, Direction::Right, 2), None);
+    }
+
+    #[test]
+    fn raycast_leaving_the_map_hits_nothing() {
+        let map = load("CCC\nCCC\n");
+        assert_eq!(map.raycast(IVec2::new(1, 1), Direction::Up, 10), None);
+        assert_eq!(map.raycast(IVec2::new(0, 0), Direction::Right, 10), None);
+    }
+
+    #[test]
+    fn targets_come_in_row_major_order() {
+        let map = load("CCT\nTWC\nCTC\n");
+        let expected = [IVec2::new(2, 0), IVec2::new(0, 1), IVec2::new(1, 2)];
+        assert_eq!(map.targets(), expected);
+        assert_eq!(map.targets(), expected);
+        assert!(Map::new(3, 3).targets().is_empty());
+    }
+
+    #[test]
+    fn border_seal_checks_every_edge() {
+        assert!(load("WWWW\nWCDW\nWWWW\n").border_is_sealed());
+        assert!(!load("WWWW\nWCDC\nWWWW\n").border_is_sealed());
+        assert!(!load("WWCW\nWCDW\nWWWW\n").border_is_sealed());
+        assert!(Map::new(3, 3)
+            .add_border(Tile::IMPASSABLE)
+            .border_is_sealed());
+    }
+
+    #[test]
+    fn open_map_edges_count_each_direction() {
+        // 12 adjacencies in a 3x3 grid, each listed once per direction
+        let map = Map::new(3, 3);
+        let edges = map.edges();
+        assert_eq!(edges.len(), 24);
+        assert!(edges.contains(&(IVec2::new(0, 0), IVec2::new(1, 0), 1)));
+        assert!(edges.contains(&(IVec2::new(1, 0), IVec2::new(0, 0), 1)));
+
+        let walled = load("CWC\nCCD\n");
+        assert_eq!(walled.edges().len(), 8);
+        assert!(walled
+            .edges()
+            .contains(&(IVec2::new(1, 1), IVec2::new(2, 1), 2)));
+
+        let dot = walled.to_dot();
+        assert!(dot.starts_with("digraph map {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("    \"1,1\" -> \"2,1\" [label=2];\n"));
+        assert_eq!(dot.lines().count(), 10);
+    }
+
+    #[test]
+    fn path_summary_counts_tiles_and_cost() {
+        let map = load("CDDT\nCWWC\n");
+        let path = [
+            IVec2::new(0, 0),
+            IVec2::new(1, 0),
+            IVec2::new(2, 0),
+            IVec2::new(3, 0),
+        ];
+        let summary = map.path_summary(&path);
+        assert_eq!(summary.counts.get(&Tile::CLEAN), Some(&1));
+        assert_eq!(summary.counts.get(&Tile::DIRTY), Some(&2));
+        assert_eq!(summary.counts.get(&Tile::TARGET), Some(&1));
+        assert_eq!(summary.total_cost, Some(5));
+
+        let through_wall =
+            map.path_summary(&[IVec2::new(0, 0), IVec2::new(0, 1), IVec2::new(1, 1)]);
+        assert_eq!(through_wall.counts.get(&Tile::IMPASSABLE), Some(&1));
+        assert_eq!(through_wall.total_cost, None);
+    }
+
+    #[test]
+    fn open_neighbors_classify_dead_ends_and_corridors() {
+        let map = load("WWWWW\nWCCCW\nWWWCW\nCCCCC\nCCCCC\n");
+        assert_eq!(map.open_neighbor_count(IVec2::new(1, 1)), 1);
+        assert_eq!(map.open_neighbor_count(IVec2::new(2, 1)), 2);
+        assert_eq!(map.open_neighbor_count(IVec2::new(3, 2)), 2);
+        assert_eq!(map.open_neighbor_count(IVec2::new(3, 3)), 4);
+        assert_eq!(map.open_neighbor_count(IVec2::new(0, 4)), 2);
+    }
+
+    #[test]
+    fn first_walkable_neighbor_prefers_up() {
+        let map = load("WWWCC\nWCWCC\nWWWCC\n");
+        assert_eq!(map.first_walkable_neighbor(IVec2::new(1, 1)), None);
+        assert_eq!(
+            map.first_walkable_neighbor(IVec2::new(3, 1)),
+            Some(IVec2::new(3, 0))
+        );
+        assert_eq!(
+            map.first_walkable_neighbor(IVec2::new(3, 0)),
+            Some(IVec2::new(3, 1))
+        );
+        assert!(map.is_walkable(IVec2::new(1, 1)));
+        assert!(!map.is_walkable(IVec2::new(0, 0)));
+        assert!(!map.is_walkable(IVec2::new(-1, 0)));
+    }
+
+    #[test]
+    fn all_pairs_distances_are_symmetric() {
+        let map = load("CCC\nWWC\nCCC\n");
+        let distances = map.all_pairs_distances();
+        let distance = |a: IVec2, b: IVec2| distances.get(&a).and_then(|row| row.get(&b)).copied();
+        for (a, row) in distances.iter() {
+            for (b, d) in row.iter() {
+                assert_eq!(distance(b, a), Some(*d));
+            }
+        }
+        assert_eq!(distance(IVec2::new(0, 0), IVec2::new(0, 0)), Some(0));
+        assert_eq!(distance(IVec2::new(0, 0), IVec2::new(2, 0)), Some(2));
+        assert_eq!(distance(IVec2::new(0, 0), IVec2::new(0, 2)), Some(6));
+        assert_eq!(distance(IVec2::new(0, 0), IVec2::new(0, 1)), None);
+        assert_eq!(distances.len(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "all pairs distances limited to")]
+    fn all_pairs_distances_refuse_large_maps() {
+        Map::new(65, 65).all_pairs_distances();
+    }
+
+    #[test]
+    fn tile_grids_convert_both_ways() {
+        let grid = vec![
+            vec![Tile::CLEAN, Tile::DIRTY, Tile::IMPASSABLE],
+            vec![Tile::KEY, Tile::DOOR, Tile::TARGET],
+        ];
+        let map = Map::from(grid.clone());
+        assert_eq!(map.dimensions(), IVec2::new(3, 2));
+        assert_eq!(map.to_string(), "CDW\nKLT\n");
+        assert_eq!(Vec::<Vec<Tile>>::from(map.clone()), grid);
+
+        // Objects are flattened onto the terrain on the way out
+        let mut layered = map;
+        layered.set_layer_tile(Layer::Objects, IVec2::new(0, 0), Tile::TARGET);
+        let flattened: Vec<Vec<Tile>> = layered.into();
+        assert_eq!(flattened[0][0], Tile::TARGET);
+        assert_eq!(flattened[1], grid[1]);
+    }
+
+    #[test]
+    fn excluded_neighbors_are_omitted() {
+        let map = Map::new(3, 3);
+        let center = IVec2::new(1, 1);
+        let occupied = HashSet::from([IVec2::new(1, 0), IVec2::new(0, 0)]);
+        let neighbors = map.get_neighbors_excluding(&center, &occupied);
+        assert_eq!(neighbors.len(), 3);
+        assert!(!neighbors.contains_key(&IVec2::new(1, 0)));
+        assert_eq!(
+            neighbors
+                .get(&IVec2::new(2, 1))
+                .map(|(direction, _)| *direction),
+            Some(Direction::Right)
+        );
+        assert_eq!(
+            map.get_neighbors_excluding(&center, &HashSet::new()).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn growing_keeps_the_original_centered() {
+        let original = load("CDC\nKTK\nCDC\n");
+        let mut map = original.clone();
+        map.resize_centered(5, 5, Tile::IMPASSABLE);
+        assert_eq!(map.to_string(), "WWWWW\nWCDCW\nWKTKW\nWCDCW\nWWWWW\n");
+
+        map.resize_centered(3, 3, Tile::IMPASSABLE);
+        assert_eq!(map.to_string(), original.to_string());
+
+        // Odd changes put the extra column on the right
+        map.resize_centered(4, 3, Tile::CLEAN);
+        assert_eq!(map.to_string(), "CDCC\nKTKC\nCDCC\n");
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn image_export_scales_each_tile() {
+        let map = load("CW\nDT\nKL\n");
+        let image = map.to_image(4);
+        assert_eq!(image.dimensions(), (8, 12));
+        assert_eq!(image.get_pixel(0, 0), image.get_pixel(3, 3));
+        assert_ne!(image.get_pixel(3, 0), image.get_pixel(4, 0));
+        assert_eq!(image.get_pixel(4, 0).0, [40, 40, 40, 255]);
+    }
+
+    #[test]
+    fn portal_exits_are_listed_as_neighbors() {
+        let mut map = Map::new(5, 1);
+        map.add_portal(IVec2::new(0, 0), IVec2::new(4, 0), false);
+        let neighbors = map.get_neighbors_with_portals(&IVec2::new(0, 0));
+        assert_eq!(neighbors.len(), 2);
+        assert_eq!(
+            neighbors.get(&IVec2::new(1, 0)).map(|(kind, _)| *kind),
+            Some(EdgeKind::Step(Direction::Right))
+        );
+        assert_eq!(
+            neighbors.get(&IVec2::new(4, 0)).map(|(kind, _)| *kind),
+            Some(EdgeKind::Teleport)
+        );
+        // One-directional portals have no edge back
+        let exit = map.get_neighbors_with_portals(&IVec2::new(4, 0));
+        assert!(!exit.contains_key(&IVec2::new(0, 0)));
+
+        // An exit next to the entrance stays a plain step
+        map.add_portal(IVec2::new(2, 0), IVec2::new(3, 0), false);
+        assert_eq!(
+            map.get_neighbors_with_portals(&IVec2::new(2, 0))
+                .get(&IVec2::new(3, 0))
+                .map(|(kind, _)| *kind),
+            Some(EdgeKind::Step(Direction::Right))
+        );
+    }
+
+    #[test]
+    fn dimensions_pair_width_and_height() {
+        let map = load("CCCC\nCCCC\nCCCC\n");
+        assert_eq!(map.dimensions(), IVec2::new(4, 3));
+        assert_eq!(map.dimensions().x as usize, map.width());
+        assert!(IVec2::new(3, 2).cmplt(map.dimensions()).all());
+        assert!(!IVec2::new(4, 0).cmplt(map.dimensions()).all());
+        assert_eq!(Map::default().dimensions(), IVec2::ZERO);
+    }
+
+    #[test]
+    fn stepping_off_the_right_edge_follows_the_wrap_mode() {
+        let map = load("CCC\nCCC\n");
+        let edge = IVec2::new(2, 1);
+        assert_eq!(
+            map.step(edge, Direction::Right, WrapMode::Clamp),
+            Some(edge)
+        );
+        assert_eq!(
+            map.step(edge, Direction::Right, WrapMode::Wrap),
+            Some(IVec2::new(0, 1))
+        );
+        assert_eq!(map.step(edge, Direction::Right, WrapMode::None), None);
+        for wrap_mode in [WrapMode::Clamp, WrapMode::Wrap, WrapMode::None] {
+            assert_eq!(
+                map.step(edge, Direction::Left, wrap_mode),
+                Some(IVec2::new(1, 1))
+            );
+        }
+    }
+
+    #[test]
+    fn passable_mask_marks_corridor_openings() {
+        let map = load("WWWWW\nCCCCC\nWWCWW\nWWCWW\n");
+        assert_eq!(map.passable_mask(IVec2::new(1, 1)), 0b1100);
+        assert_eq!(map.passable_mask(IVec2::new(2, 3)), 0b0001);
+        assert_eq!(map.passable_mask(IVec2::new(2, 2)), 0b0011);
+        assert_eq!(map.passable_mask(IVec2::new(2, 1)), 0b1110);
+        assert_eq!(map.passable_mask(IVec2::new(0, 1)), 0b1000);
+
+        // On a wrapped map the corridor continues across the seam, while the wall beyond the bottom stays closed
+        let mut wrapped = map.clone();
+        wrapped.set_wrap_mode(WrapMode::Wrap);
+        assert_eq!(wrapped.passable_mask(IVec2::new(0, 1)), 0b1100);
+        assert_eq!(wrapped.passable_mask(IVec2::new(4, 1)), 0b1100);
+        assert_eq!(wrapped.passable_mask(IVec2::new(2, 3)), 0b0001);
+        assert_eq!(
+            wrapped.open_neighbor_count(IVec2::new(0, 1)) as usize,
+            wrapped
+                .neighbors_iter(IVec2::new(0, 1))
+                .filter(|(_, _, tile)| tile.is_passable())
+                .count()
+        );
+    }
+
+    #[test]
+    fn spiral_starts_at_the_center_and_turns_clockwise() {
+        let map = load("CCC\nCCC\nCCC\n");
+        let spiral: Vec<IVec2> = map
+            .iter_order(TraversalOrder::SpiralOut)
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(
+            spiral[..6],
+            [
+                IVec2::new(1, 1),
+                IVec2::new(2, 1),
+                IVec2::new(2, 2),
+                IVec2::new(1, 2),
+                IVec2::new(0, 2),
+                IVec2::new(0, 1),
+            ]
+        );
+        assert_eq!(spiral.len(), 9);
+        assert_eq!(spiral.iter().collect::<HashSet<_>>().len(), 9);
+
+        let inward: Vec<IVec2> = map
+            .iter_order(TraversalOrder::SpiralIn)
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(inward.last(), Some(&IVec2::new(1, 1)));
+        assert_eq!(
+            map.iter_order(TraversalOrder::ColumnMajor)
+                .nth(1)
+                .unwrap()
+                .0,
+            IVec2::new(0, 1)
+        );
+    }
+
+    #[test]
+    fn hamming_distance_counts_differing_cells() {
+        let map = load("CCCC\nCWWC\nCCCC\n");
+        let changed = load("DCCC\nCWCC\nCCCT\nCCCC\n");
+        assert!(matches!(
+            map.hamming_distance(&changed),
+            Err(MapError::DimensionMismatch { .. })
+        ));
+        let changed = load("DCCC\nCWCC\nCCWT\n");
+        assert_eq!(map.hamming_distance(&changed).unwrap(), 4);
+        assert_eq!(changed.hamming_distance(&map).unwrap(), 4);
+        assert_eq!(map.hamming_distance(&map).unwrap(), 0);
+    }
+
+    #[test]
+    fn knight_offsets_reach_only_on_map_cells() {
+        let map = load("CCCCC\nCCCCC\nCCCCC\nCCCCC\nCCCCC\n");
+        let knight = [
+            IVec2::new(1, 2),
+            IVec2::new(2, 1),
+            IVec2::new(2, -1),
+            IVec2::new(1, -2),
+            IVec2::new(-1, -2),
+            IVec2::new(-2, -1),
+            IVec2::new(-2, 1),
+            IVec2::new(-1, 2),
+        ];
+        assert_eq!(map.neighbors_by_offsets(IVec2::new(2, 2), &knight).len(), 8);
+        let corner: Vec<IVec2> = map
+            .neighbors_by_offsets(IVec2::new(0, 0), &knight)
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(corner, vec![IVec2::new(1, 2), IVec2::new(2, 1)]);
+        let edge: Vec<IVec2> = map
+            .neighbors_by_offsets(IVec2::new(4, 2), &knight)
+            .into_iter()
+            .map(|(pos, _)| pos)
+            .collect();
+        assert_eq!(
+            edge,
+            vec![
+                IVec2::new(3, 0),
+                IVec2::new(2, 1),
+                IVec2::new(2, 3),
+                IVec2::new(3, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn clearing_a_filled_map_keeps_its_size() {
+        let mut map = load("CDC\nDWT\n");
+        map.fill(Tile::IMPASSABLE);
+        assert_eq!(map.get_all_of_type(Tile::IMPASSABLE).len(), 6);
+        map.clear();
+        assert_eq!(map.dimensions(), IVec2::new(3, 2));
+        assert!(map
+            .get_tile_iterator()
+            .all(|(_, tile)| *tile == Tile::CLEAN));
+    }
+
+    #[test]
+    fn avoiding_dirt_blocks_the_only_route() {
+        let map = load("CWC\nCWC\nDDC\n");
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 0));
+        assert!(map.path_exists_avoiding(start, goal, &[]));
+        assert!(!map.path_exists_avoiding(start, goal, &[Tile::DIRTY]));
+        assert!(map.path_exists_avoiding(IVec2::new(0, 2), IVec2::new(0, 0), &[Tile::DIRTY]));
+    }
+
+    #[test]
+    fn majority_rule_removes_a_stray_wall() {
+        let map = load("CCCCC\nCCCCC\nCCWCC\nCCCCC\nCCCCC\n");
+        let majority = |tile: &Tile, neighborhood: &[Option<&Tile>; 8]| {
+            let present = neighborhood.iter().flatten().count();
+            let walls = neighborhood
+                .iter()
+                .flatten()
+                .filter(|neighbor| !neighbor.is_passable())
+                .count();
+            if walls * 2 > present {
+                Tile::IMPASSABLE
+            } else if walls * 2 < present {
+                Tile::CLEAN
+            } else {
+                *tile
+            }
+        };
+        let smoothed = map.apply_rule(majority);
+        assert_eq!(smoothed.get_tile(IVec2::new(2, 2)), Some(&Tile::CLEAN));
+        assert!(smoothed.get_all_of_type(Tile::IMPASSABLE).is_empty());
+        assert_eq!(smoothed.dimensions(), map.dimensions());
+    }
+
+    #[test]
+    fn two_targets_violate_exactly_one_target() {
+        let map = load("WWWWW\nWTCTW\nWWWWW\n");
+        let rules = [
+            ValidationRule::ExactlyOneTarget,
+            ValidationRule::SealedBorder,
+            ValidationRule::AtLeastOnePassable,
+        ];
+        let violations = map.validate(&rules).unwrap_err();
+        assert_eq!(violations, vec![ValidationError::TargetCount(2)]);
+        assert_eq!(violations[0].rule(), ValidationRule::ExactlyOneTarget);
+
+        let open = load("TC\nCC\n");
+        assert_eq!(
+            open.validate(&rules),
+            Err(vec![ValidationError::OpenBorder])
+        );
+        assert_eq!(open.validate(&[ValidationRule::ExactlyOneTarget]), Ok(()));
+    }
+
+    #[test]
+    fn neighbor_helpers_agree_across_a_wrapped_seam() {
+        let mut map = load("CWWWC\nWWWWW\nCWWWW\n");
+        map.set_wrap_mode(WrapMode::Wrap);
+        let corner = IVec2::new(0, 0);
+        let iterated: Vec<IVec2> = map
+            .neighbors_iter(corner)
+            .filter(|(_, _, tile)| tile.is_passable())
+            .map(|(pos, _, _)| pos)
+            .collect();
+        assert_eq!(iterated, vec![IVec2::new(0, 2), IVec2::new(4, 0)]);
+        assert_eq!(map.first_walkable_neighbor(corner), Some(IVec2::new(0, 2)));
+        let sorted: Vec<IVec2> = map
+            .neighbors_sorted_toward(&corner, IVec2::new(4, 0))
+            .into_iter()
+            .map(|(pos, _, _)| pos)
+            .collect();
+        assert_eq!(sorted, vec![IVec2::new(4, 0), IVec2::new(0, 2)]);
+
+        let open = |_: &Tile, neighborhood: &[Option<&Tile>; 8]| {
+            let count = neighborhood
+                .iter()
+                .filter(|neighbor| neighbor.is_some_and(|tile| tile.is_passable()))
+                .count();
+            if count > 0 {
+                Tile::CLEAN
+            } else {
+                Tile::IMPASSABLE
+            }
+        };
+        // The corner sees the opposite corners across the seam, and no neighbor is ever off the map
+        let stepped = map.apply_rule(open);
+        assert_eq!(stepped.get_tile(corner), Some(&Tile::CLEAN));
+        map.set_wrap_mode(WrapMode::None);
+        assert_eq!(
+            map.apply_rule(open).get_tile(corner),
+            Some(&Tile::IMPASSABLE)
+        );
     }
 }
@@ -0,0 +1,1675 @@
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use glam::IVec2;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::Direction,
+    agent::AgentState,
+    map::{CostModel, GridMap, Map, Tile, WrapMode},
+};
+
+/**
+ * Result of a search over the map.
+ * Exhausted is distinct from NoPath so real-time callers can tell "gave up" apart from "unreachable".
+ */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SearchOutcome {
+    // Path from start to goal, including both ends
+    // A start equal to the goal is found as the single position path, a route of no moves
+    Found(Vec<IVec2>),
+    // The frontier emptied before the goal was reached
+    NoPath,
+    // The expansion budget ran out before the goal was reached
+    Exhausted,
+}
+
+/**
+ * Anything the pathfinders can search over.
+ * Nodes are identified by an IVec2 so non-grid graphs such as waypoint graphs or navmeshes can reuse the searches.
+ */
+pub trait Graph {
+    // Reachable nodes from the given node along with the cost of moving there
+    // Returned lazily so searches can expand nodes without allocating a list for each one
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_;
+
+    // Estimated cost from one node to another, which must never exceed the true cost for A* to stay optimal
+    // Graphs with shortcuts the metric does not know about, such as portals, override this to account for them
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        metric.distance(&from, &to)
+    }
+}
+
+// Moving costs the movement cost of the tile being entered
+// Portal exits count as a neighbor of their entrance so searches route through them
+impl Graph for Map {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        costed_neighbors(self, node, Tile::movement_cost)
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        let wrap = (self.wrap_mode() == WrapMode::Wrap).then(|| self.dimensions());
+        estimate_with_portals(
+            |a, b| wrapped_distance(metric, a, b, wrap),
+            from,
+            to,
+            self.portals(),
+        )
+    }
+}
+
+// Neighbors of a position on the map and the exit of any portal on it, costed by the tile entered
+// Tiles the cost function returns None for are left out
+fn costed_neighbors<'a>(
+    map: &'a Map,
+    node: IVec2,
+    cost: impl Fn(&Tile) -> Option<i32> + Copy + 'a,
+) -> impl Iterator<Item = (IVec2, i32)> + 'a {
+    let steps = move || {
+        map.moves_iter(node)
+            .filter_map(move |(pos, _direction, tile)| cost(tile).map(|cost| (pos, cost)))
+    };
+    // A portal exit that is also an adjacent tile is already listed
+    let portal = map
+        .get_portal(&node)
+        .filter(|exit| !steps().any(|(pos, _)| pos == *exit))
+        .and_then(|exit| map.get_tile(exit).and_then(cost).map(|cost| (exit, cost)));
+    steps().chain(portal)
+}
+
+// Distance under the metric, measured the short way around when the map wraps with the given size
+fn wrapped_distance(metric: Metric, a: IVec2, b: IVec2, wrap: Option<IVec2>) -> i32 {
+    let delta = (a - b).abs();
+    let delta = match wrap {
+        Some(size) => delta.min(size - delta),
+        None => delta,
+    };
+    metric.distance(&delta, &IVec2::ZERO)
+}
+
+// Lower bound on the cost between two positions when any portal may shortcut the way
+// Taking a portal is at least one move, so this never overestimates as long as every move costs at least 1
+// The shortcut goes to the nearest entrance and leaves from the exit nearest the goal, which may not be linked
+fn estimate_with_portals(
+    distance: impl Fn(IVec2, IVec2) -> i32,
+    from: IVec2,
+    to: IVec2,
+    portals: impl Iterator<Item = (IVec2, IVec2)> + Clone,
+) -> i32 {
+    let direct = distance(from, to);
+    let to_entrance = portals
+        .clone()
+        .map(|(entrance, _)| distance(from, entrance))
+        .min();
+    let from_exit = portals.map(|(_, exit)| distance(exit, to)).min();
+    match to_entrance.zip(from_exit) {
+        Some((to_entrance, from_exit)) => direct.min(to_entrance + 1 + from_exit),
+        None => direct,
+    }
+}
+
+// Positions treated as impassable on top of the map, such as other agents
+pub type Blocked = HashSet<IVec2>;
+
+/**
+ * View of a map with an extra set of blocked positions.
+ * Lets each agent route around moving obstacles without cloning or mutating the shared map.
+ */
+pub struct DynamicObstacles<'a> {
+    map: &'a Map,
+    blocked: &'a Blocked,
+}
+
+impl<'a> DynamicObstacles<'a> {
+    pub fn new(map: &'a Map, blocked: &'a Blocked) -> Self {
+        DynamicObstacles { map, blocked }
+    }
+}
+
+impl Graph for DynamicObstacles<'_> {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        self.map
+            .neighbors(node)
+            .filter(|(pos, _)| !self.blocked.contains(pos))
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        self.map.heuristic(metric, from, to)
+    }
+}
+
+/**
+ * View of a map charging moves by a cost model rather than each tile's built in movement cost.
+ */
+pub struct CostModelView<'a> {
+    map: &'a Map,
+    model: &'a CostModel,
+}
+
+impl<'a> CostModelView<'a> {
+    pub fn new(map: &'a Map, model: &'a CostModel) -> Self {
+        CostModelView { map, model }
+    }
+}
+
+impl Graph for CostModelView<'_> {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        costed_neighbors(self.map, node, |tile| self.model.cost(tile))
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        self.map.heuristic(metric, from, to)
+    }
+}
+
+/**
+ * View of a map as seen by an agent carrying the given state, so doors it holds a key for can be planned through.
+ */
+pub struct AgentStateView<'a> {
+    map: &'a Map,
+    state: &'a AgentState,
+}
+
+impl<'a> AgentStateView<'a> {
+    pub fn new(map: &'a Map, state: &'a AgentState) -> Self {
+        AgentStateView { map, state }
+    }
+}
+
+impl Graph for AgentStateView<'_> {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        costed_neighbors(self.map, node, |tile| tile.movement_cost_for(self.state))
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        self.map.heuristic(metric, from, to)
+    }
+}
+
+/**
+ * View of a map where moves follow a custom set of offsets, such as knight moves, instead of the 4 cardinal directions.
+ * The heuristic used by astar assumes unit moves, so use dijkstra or Metric::Zero when offsets cover more ground.
+ */
+pub struct OffsetView<'a> {
+    map: &'a Map,
+    offsets: &'a [IVec2],
+}
+
+impl<'a> OffsetView<'a> {
+    pub fn new(map: &'a Map, offsets: &'a [IVec2]) -> Self {
+        OffsetView { map, offsets }
+    }
+}
+
+impl Graph for OffsetView<'_> {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        self.map
+            .neighbors_by_offsets(node, self.offsets)
+            .into_iter()
+            .filter_map(|(pos, tile)| tile.movement_cost().map(|cost| (pos, cost)))
+    }
+}
+
+/**
+ * View of a map only allowing positions with enough clearance for a large agent.
+ * The agent occupies a square whose top-left corner is its position.
+ */
+pub struct ClearanceView<'a> {
+    map: &'a Map,
+    clearance: GridMap<u32>,
+    min_clearance: u32,
+}
+
+impl<'a> ClearanceView<'a> {
+    pub fn new(map: &'a Map, min_clearance: u32) -> Self {
+        ClearanceView {
+            map,
+            clearance: map.clearance_map(),
+            min_clearance,
+        }
+    }
+}
+
+impl Graph for ClearanceView<'_> {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        self.map
+            .neighbors(node)
+            .filter(|(pos, _)| self.clearance.get(pos).copied().unwrap_or(0) >= self.min_clearance)
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        self.map.heuristic(metric, from, to)
+    }
+}
+
+/**
+ * Precomputed neighbor lists for a map that does not change.
+ * Lookups borrow the stored lists instead of allocating, rebuild it if the map is edited.
+ */
+#[derive(Clone, Debug)]
+pub struct Adjacency {
+    width: usize,
+    // Neighbors and move costs for each position, indexed in row-major order
+    neighbors: Vec<Vec<(IVec2, i32)>>,
+    // Portal entrances and exits of the map, so the heuristic can account for them
+    portals: Vec<(IVec2, IVec2)>,
+    // Size of the map if its edges wrap, so the heuristic can measure across the seam
+    wrap: Option<IVec2>,
+}
+
+impl Adjacency {
+    pub fn new(map: &Map) -> Self {
+        Adjacency {
+            width: map.width(),
+            neighbors: (0..map.width() * map.height())
+                .map(|idx| map.neighbors(map.from_index(idx)).collect())
+                .collect(),
+            portals: map.portals().collect(),
+            wrap: (map.wrap_mode() == WrapMode::Wrap).then(|| map.dimensions()),
+        }
+    }
+
+    // Neighbors of a position, empty if the position is off the map
+    pub fn neighbors_of(&self, pos: IVec2) -> &[(IVec2, i32)] {
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.width {
+            return &[];
+        }
+        self.neighbors
+            .get(pos.y as usize * self.width + pos.x as usize)
+            .map_or(&[], |neighbors| neighbors.as_slice())
+    }
+}
+
+impl Graph for Adjacency {
+    fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+        self.neighbors_of(node).iter().copied()
+    }
+
+    fn heuristic(&self, metric: Metric, from: IVec2, to: IVec2) -> i32 {
+        estimate_with_portals(
+            |a, b| wrapped_distance(metric, a, b, self.wrap),
+            from,
+            to,
+            self.portals.iter().copied(),
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct PositionNode {
+    position: IVec2,
+    cost: i32,
+}
+
+impl PartialOrd for PositionNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Ordering is reversed so the heap pops the lowest cost first
+impl Ord for PositionNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.cost.cmp(&self.cost)
+    }
+}
+
+pub fn manhattan_distance(a: &IVec2, b: &IVec2) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+/**
+ * Distance estimate used as the A* heuristic.
+ * On 4-connected grids where every move costs at least 1, Manhattan is the tightest estimate that never overestimates,
+ * so it expands the fewest nodes while still finding optimal paths. Euclidean and Chebyshev never exceed it and stay
+ * optimal but expand more, and Zero turns A* into Dijkstra. Searches pass the metric to Graph::heuristic, which
+ * lets maps with portals or wrapped edges lower the estimate so it stays admissible.
+ */
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Metric {
+    #[default]
+    Manhattan,
+    // Straight line distance rounded down
+    Euclidean,
+    // Largest difference along either axis
+    Chebyshev,
+    Zero,
+}
+
+impl Metric {
+    pub fn distance(&self, a: &IVec2, b: &IVec2) -> i32 {
+        let delta = (*a - *b).abs();
+        match self {
+            Metric::Manhattan => manhattan_distance(a, b),
+            Metric::Euclidean => delta.as_vec2().length() as i32,
+            Metric::Chebyshev => delta.max_element(),
+            Metric::Zero => 0,
+        }
+    }
+}
+
+// A* search using the manhattan distance heuristic
+// max_expansions limits how many nodes may be expanded before giving up
+pub fn astar<G: Graph>(
+    graph: &G,
+    start: IVec2,
+    goal: IVec2,
+    max_expansions: Option<usize>,
+) -> SearchOutcome {
+    astar_with_metric(graph, start, goal, max_expansions, Metric::Manhattan)
+}
+
+// A* search using the given metric as its heuristic
+pub fn astar_with_metric<G: Graph>(
+    graph: &G,
+    start: IVec2,
+    goal: IVec2,
+    max_expansions: Option<usize>,
+    metric: Metric,
+) -> SearchOutcome {
+    search(graph, start, goal, max_expansions, metric).0
+}
+
+// A* search returning the path along with its total cost, the sum of the costs of every move
+// With unit costs the total is path.len() - 1
+pub fn astar_with_cost<G: Graph>(
+    graph: &G,
+    start: IVec2,
+    goal: IVec2,
+) -> Option<(Vec<IVec2>, i32)> {
+    match search(graph, start, goal, None, Metric::Manhattan) {
+        (SearchOutcome::Found(path), Some(cost)) => Some((path, cost)),
+        _ => None,
+    }
+}
+
+// Dijkstra search, equivalent to A* without a heuristic
+pub fn dijkstra<G: Graph>(
+    graph: &G,
+    start: IVec2,
+    goal: IVec2,
+    max_expansions: Option<usize>,
+) -> SearchOutcome {
+    search(graph, start, goal, max_expansions, Metric::Zero).0
+}
+
+fn search<G: Graph>(
+    graph: &G,
+    start: IVec2,
+    goal: IVec2,
+    max_expansions: Option<usize>,
+    metric: Metric,
+) -> (SearchOutcome, Option<i32>) {
+    // Already being at the goal is a solved search, not a stuck one, even with no expansion budget
+    if start == goal {
+        return (SearchOutcome::Found(vec![start]), Some(0));
+    }
+    let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+    let mut cost_so_far: HashMap<IVec2, i32> = HashMap::new();
+    cost_so_far.insert(start, 0);
+    let mut frontier = BinaryHeap::new();
+    frontier.push(PositionNode {
+        position: start,
+        cost: 0,
+    });
+
+    let mut expansions = 0;
+    while let Some(current) = frontier.pop() {
+        if current.position == goal {
+            debug!("search reached {} after {} expansions", goal, expansions);
+            return (
+                SearchOutcome::Found(reconstruct_path(&came_from, start, goal)),
+                Some(cost_so_far[&goal]),
+            );
+        }
+        if max_expansions.is_some_and(|max| expansions >= max) {
+            debug!("search exhausted its budget of {} expansions", expansions);
+            return (SearchOutcome::Exhausted, None);
+        }
+        expansions += 1;
+        trace!("expanding {}", current.position);
+
+        for (neighbor, step_cost) in graph.neighbors(current.position) {
+            let cost = cost_so_far[&current.position] + step_cost;
+            if cost_so_far.get(&neighbor).is_none_or(|&known| cost < known) {
+                cost_so_far.insert(neighbor, cost);
+                frontier.push(PositionNode {
+                    position: neighbor,
+                    cost: cost + graph.heuristic(metric, neighbor, goal),
+                });
+                came_from.insert(neighbor, current.position);
+            }
+        }
+    }
+
+    debug!(
+        "search found no path to {} after {} expansions",
+        goal, expansions
+    );
+    (SearchOutcome::NoPath, None)
+}
+
+/**
+ * Cache of computed paths keyed by start, goal, and the map checksum.
+ * The checksum covers objects, portals, one-way tiles and the edge rules as well as terrain, so entries for an older
+ * version of the map are dropped as soon as a map changed in any way that affects routing is queried.
+ */
+#[derive(Default)]
+pub struct PathCache {
+    paths: HashMap<(IVec2, IVec2, u64), Option<Vec<IVec2>>>,
+    checksum: Option<u64>,
+    hits: usize,
+    misses: usize,
+}
+
+impl PathCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Number of queries served from the cache
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    // Number of queries that required a search
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+        self.checksum = None;
+    }
+}
+
+// A* path from start to goal, served from the cache when the same query was made on an unchanged map
+pub fn find_path_cached(
+    cache: &mut PathCache,
+    map: &Map,
+    start: IVec2,
+    goal: IVec2,
+) -> Option<Vec<IVec2>> {
+    let checksum = map.checksum();
+    if cache.checksum != Some(checksum) {
+        cache.paths.clear();
+        cache.checksum = Some(checksum);
+    }
+
+    let key = (start, goal, checksum);
+    if let Some(path) = cache.paths.get(&key) {
+        cache.hits += 1;
+        return path.clone();
+    }
+    cache.misses += 1;
+    let path = match astar(map, start, goal, None) {
+        SearchOutcome::Found(path) => Some(path),
+        _ => None,
+    };
+    cache.paths.insert(key, path.clone());
+    path
+}
+
+// Visits every waypoint starting from start, always heading to the cheapest remaining waypoint next
+// This nearest neighbor ordering is a heuristic and is not guaranteed to find the shortest overall route
+// Returns None if any waypoint is unreachable
+pub fn route_through(map: &Map, start: IVec2, waypoints: &[IVec2]) -> Option<Vec<IVec2>> {
+    let mut route = vec![start];
+    let mut remaining = waypoints.to_vec();
+    let mut current = start;
+
+    while !remaining.is_empty() {
+        let (index, leg) = remaining
+            .iter()
+            .enumerate()
+            .filter_map(|(index, waypoint)| {
+                astar_with_cost(map, current, *waypoint).map(|(path, cost)| (index, path, cost))
+            })
+            .min_by_key(|(_, _, cost)| *cost)
+            .map(|(index, path, _)| (index, path))?;
+        // The first position of each leg is the end of the previous one
+        route.extend(leg.into_iter().skip(1));
+        current = remaining.swap_remove(index);
+    }
+
+    Some(route)
+}
+
+// Total cost of the cheapest path between two positions, None if unreachable
+// With unit costs this is the number of moves
+pub fn shortest_path_length(map: &Map, start: IVec2, goal: IVec2) -> Option<i32> {
+    astar_with_cost(map, start, goal).map(|(_, cost)| cost)
+}
+
+// Visits every waypoint starting from start in the order with the lowest total cost
+// Uses Held-Karp dynamic programming, which is O(2^n * n^2) so it is only practical up to around 12 waypoints
+// Returns None if any waypoint is unreachable
+pub fn optimal_route(map: &Map, start: IVec2, waypoints: &[IVec2]) -> Option<Vec<IVec2>> {
+    let count = waypoints.len();
+    let from_start: Vec<i32> = waypoints
+        .iter()
+        .map(|waypoint| shortest_path_length(map, start, *waypoint))
+        .collect::<Option<_>>()?;
+    let mut between = vec![vec![0; count]; count];
+    for i in 0..count {
+        for j in 0..count {
+            if i != j {
+                between[i][j] = shortest_path_length(map, waypoints[i], waypoints[j])?;
+            }
+        }
+    }
+
+    // best[mask][last] is the cheapest way to visit the waypoints in mask, ending at last
+    let mut best = vec![vec![i32::MAX; count]; 1 << count];
+    let mut previous = vec![vec![None; count]; 1 << count];
+    for (last, distance) in from_start.iter().enumerate() {
+        best[1 << last][last] = *distance;
+    }
+    for mask in 1..(1usize << count) {
+        for last in 0..count {
+            if best[mask][last] == i32::MAX {
+                continue;
+            }
+            for next in (0..count).filter(|next| mask & (1 << next) == 0) {
+                let cost = best[mask][last] + between[last][next];
+                let next_mask = mask | (1 << next);
+                if cost < best[next_mask][next] {
+                    best[next_mask][next] = cost;
+                    previous[next_mask][next] = Some(last);
+                }
+            }
+        }
+    }
+
+    // Walk the choices backwards from the cheapest final waypoint to recover the order
+    let full = (1 << count) - 1;
+    let mut order = Vec::with_capacity(count);
+    let mut last = (0..count).min_by_key(|last| best[full][*last]);
+    let mut mask = full;
+    while let Some(current) = last {
+        order.push(waypoints[current]);
+        last = previous[mask][current];
+        mask &= !(1 << current);
+    }
+    order.reverse();
+
+    let mut route = vec![start];
+    let mut current = start;
+    for waypoint in order {
+        match astar(map, current, waypoint, None) {
+            // The first position of each leg is the end of the previous one
+            SearchOutcome::Found(leg) => route.extend(leg.into_iter().skip(1)),
+            _ => return None,
+        }
+        current = waypoint;
+    }
+    Some(route)
+}
+
+// Breadth first search that stops at the first position satisfying is_goal
+// The returned path leads to the matching tile that is the fewest moves away, ignoring tile costs
+pub fn find_path_until<F: Fn(IVec2, &Tile) -> bool>(
+    map: &Map,
+    start: IVec2,
+    is_goal: F,
+) -> Option<Vec<IVec2>> {
+    let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+    let mut frontier = VecDeque::from([start]);
+    let mut visited = HashSet::from([start]);
+
+    while let Some(current) = frontier.pop_front() {
+        if map
+            .get_tile(current)
+            .is_some_and(|tile| is_goal(current, tile))
+        {
+            return Some(reconstruct_path(&came_from, start, current));
+        }
+        for (neighbor, _cost) in map.neighbors(current) {
+            if visited.insert(neighbor) {
+                came_from.insert(neighbor, current);
+                frontier.push_back(neighbor);
+            }
+        }
+    }
+
+    None
+}
+
+// Walks came_from backwards from the goal to produce a start-to-goal path
+fn reconstruct_path(came_from: &HashMap<IVec2, IVec2>, start: IVec2, goal: IVec2) -> Vec<IVec2> {
+    let mut path = vec![goal];
+    let mut current = goal;
+    while current != start {
+        current = came_from[&current];
+        path.push(current);
+    }
+    path.reverse();
+    path
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FacingNode {
+    position: IVec2,
+    facing: Option<Direction>,
+    cost: i32,
+}
+
+impl PartialOrd for FacingNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Ordering is reversed so the heap pops the lowest cost first
+impl Ord for FacingNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.cost.cmp(&self.cost)
+    }
+}
+
+/**
+ * A* over (position, facing) states for agents that pay extra to change direction.
+ * Each move costs the same as on the map's Graph, plus turn_penalty when its direction differs from the current facing.
+ * Jumping through a portal keeps the current facing.
+ */
+pub struct AStarWithFacing {
+    turn_penalty: i32,
+}
+
+impl AStarWithFacing {
+    // A negative penalty would let turning in circles lower the cost forever
+    pub fn new(turn_penalty: i32) -> Self {
+        assert!(
+            turn_penalty >= 0,
+            "turn penalty must not be negative, got {}",
+            turn_penalty
+        );
+        AStarWithFacing { turn_penalty }
+    }
+
+    // Finds the cheapest path from start to goal, None if the goal is unreachable
+    // A start facing of None means the first move is never penalized
+    pub fn find_path(
+        &self,
+        map: &Map,
+        start: IVec2,
+        start_facing: Option<Direction>,
+        goal: IVec2,
+    ) -> Option<Vec<IVec2>> {
+        type State = (IVec2, Option<Direction>);
+        let start_state: State = (start, start_facing);
+        let mut came_from: HashMap<State, State> = HashMap::new();
+        let mut cost_so_far: HashMap<State, i32> = HashMap::from([(start_state, 0)]);
+        let mut frontier = BinaryHeap::from([FacingNode {
+            position: start,
+            facing: start_facing,
+            cost: 0,
+        }]);
+
+        while let Some(current) = frontier.pop() {
+            let current_state = (current.position, current.facing);
+            if current.position == goal {
+                let mut path = vec![current.position];
+                let mut state = current_state;
+                while let Some(previous) = came_from.get(&state) {
+                    path.push(previous.0);
+                    state = *previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for (neighbor, step_cost) in map.neighbors(current.position) {
+                // Portal exits are not reached by a step in any direction
+                let direction = map
+                    .moves_iter(current.position)
+                    .find(|(pos, _, _)| *pos == neighbor)
+                    .map(|(_, direction, _)| direction);
+                let turn = direction.is_some_and(|direction| {
+                    current.facing.is_some_and(|facing| facing != direction)
+                });
+                let cost = cost_so_far[&current_state]
+                    + step_cost
+                    + if turn { self.turn_penalty } else { 0 };
+                let facing = direction.or(current.facing);
+                let next_state = (neighbor, facing);
+                if cost_so_far
+                    .get(&next_state)
+                    .is_none_or(|&known| cost < known)
+                {
+                    cost_so_far.insert(next_state, cost);
+                    frontier.push(FacingNode {
+                        position: neighbor,
+                        facing,
+                        cost: cost + map.heuristic(Metric::Manhattan, neighbor, goal),
+                    });
+                    came_from.insert(next_state, current_state);
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/**
+ * Space-time cells and moves claimed by agents that have already been planned.
+ */
+#[derive(Default)]
+struct Reservations {
+    // Positions occupied at each time step
+    cells: HashSet<(IVec2, u32)>,
+    // Moves made from the first position to the second between a time step and the next
+    moves: HashSet<(IVec2, IVec2, u32)>,
+    // Goals mapped to the time step their agent arrives and parks on them
+    parked: HashMap<IVec2, u32>,
+    // Last time step each position is occupied, parked agents occupy their goal from arrival onward
+    last_occupied: HashMap<IVec2, u32>,
+    // Last time step of any reserved path
+    latest: u32,
+}
+
+impl Reservations {
+    fn reserve(&mut self, path: &[IVec2]) {
+        for (time, pos) in path.iter().enumerate() {
+            self.cells.insert((*pos, time as u32));
+            self.last_occupied.insert(*pos, time as u32);
+        }
+        for (time, step) in path.windows(2).enumerate() {
+            self.moves.insert((step[0], step[1], time as u32));
+        }
+        let arrival = path.len() as u32 - 1;
+        self.parked.insert(path[path.len() - 1], arrival);
+        self.latest = self.latest.max(arrival);
+    }
+
+    fn is_free(&self, pos: IVec2, time: u32) -> bool {
+        !self.cells.contains(&(pos, time))
+            && self.parked.get(&pos).is_none_or(|&since| time < since)
+    }
+
+    // Whether moving from one position to another at a time step avoids sharing a cell or swapping with another agent
+    fn can_move(&self, from: IVec2, to: IVec2, time: u32) -> bool {
+        self.is_free(to, time + 1) && !self.moves.contains(&(to, from, time))
+    }
+
+    // Whether an agent arriving at a time step can stay there without blocking a later reservation
+    fn can_park(&self, pos: IVec2, time: u32) -> bool {
+        self.last_occupied.get(&pos).is_none_or(|&last| last < time)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct TimedNode {
+    position: IVec2,
+    time: u32,
+    cost: u32,
+}
+
+impl PartialOrd for TimedNode {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Ordering is reversed so the heap pops the lowest cost first
+impl Ord for TimedNode {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        other.cost.cmp(&self.cost)
+    }
+}
+
+// Plans paths for several agents that never share a tile or swap places, giving each agent's position at every time step
+// Agents are planned in order with space-time A*, each avoiding the reservations of the agents before it
+// Every move or wait takes one time step regardless of tile costs, and agents stay on their goal once they arrive
+// Returns None if starts and goals differ in length or any agent cannot reach its goal
+pub fn cooperative_astar(map: &Map, starts: &[IVec2], goals: &[IVec2]) -> Option<Vec<Vec<IVec2>>> {
+    if starts.len() != goals.len() {
+        return None;
+    }
+    let mut reservations = Reservations::default();
+    let mut paths = Vec::with_capacity(starts.len());
+    for (start, goal) in starts.iter().zip(goals) {
+        let path = space_time_astar(map, *start, *goal, &reservations)?;
+        reservations.reserve(&path);
+        paths.push(path);
+    }
+    Some(paths)
+}
+
+// A* over (position, time) states that waits in place when the way is reserved
+// Searching stops once the time passes every reservation plus enough steps to cross the whole map
+fn space_time_astar(
+    map: &Map,
+    start: IVec2,
+    goal: IVec2,
+    reservations: &Reservations,
+) -> Option<Vec<IVec2>> {
+    if !reservations.is_free(start, 0) {
+        return None;
+    }
+    let horizon = reservations.latest + (map.width() * map.height()) as u32;
+    let mut came_from: HashMap<(IVec2, u32), (IVec2, u32)> = HashMap::new();
+    let mut visited = HashSet::from([(start, 0)]);
+    let mut frontier = BinaryHeap::from([TimedNode {
+        position: start,
+        time: 0,
+        cost: map.heuristic(Metric::Manhattan, start, goal) as u32,
+    }]);
+
+    while let Some(current) = frontier.pop() {
+        let state = (current.position, current.time);
+        if current.position == goal && reservations.can_park(goal, current.time) {
+            let mut path = vec![current.position];
+            let mut state = state;
+            while let Some(previous) = came_from.get(&state) {
+                path.push(previous.0);
+                state = *previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        if current.time >= horizon {
+            continue;
+        }
+
+        let waiting = std::iter::once(current.position);
+        let moving = map.neighbors(current.position).map(|(pos, _)| pos);
+        for next in waiting.chain(moving) {
+            let next_state = (next, current.time + 1);
+            if reservations.can_move(current.position, next, current.time)
+                && visited.insert(next_state)
+            {
+                came_from.insert(next_state, state);
+                frontier.push(TimedNode {
+                    position: next,
+                    time: current.time + 1,
+                    cost: current.time + 1 + map.heuristic(Metric::Manhattan, next, goal) as u32,
+                });
+            }
+        }
+    }
+
+    debug!("space-time search found no path to {}", goal);
+    None
+}
+
+/**
+ * Outcome of asking a planner for its next move.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Step {
+    // Move one tile in the given direction
+    Move(Direction),
+    // Jump through the portal underfoot to its exit
+    Teleport,
+    // Already standing on the goal
+    Arrived,
+    // Not at the goal and there is no move that leads toward it
+    Stuck,
+}
+
+/**
+ * Result of advancing a stepped search by one expansion.
+ */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchStep {
+    // The given position was expanded and its neighbors added to the frontier
+    Expanded(IVec2),
+    // The goal has been reached, the path is available
+    Found,
+    // The frontier is empty and the goal cannot be reached
+    Exhausted,
+}
+
+/**
+ * Stateful A* planner handing out one move per call.
+ * The search can be advanced one expansion at a time with step, or run to completion by run.
+ * The full path is planned on the first call to run and then followed.
+ * Asking for a different goal than the search is heading to starts a fresh search from the current position.
+ */
+pub struct AStar {
+    start: IVec2,
+    // Goal the search is heading to, None until the first call to step or run
+    goal: Option<IVec2>,
+    came_from: HashMap<IVec2, IVec2>,
+    cost_so_far: HashMap<IVec2, i32>,
+    frontier: BinaryHeap<PositionNode>,
+    expanded: HashSet<IVec2>,
+    found: Option<IVec2>,
+    // Heuristic guiding the search
+    metric: Metric,
+
+    position: IVec2,
+    // Remaining positions to visit, next position last
+    plan: Option<Vec<IVec2>>,
+}
+
+impl AStar {
+    pub fn new(start: IVec2) -> AStar {
+        Self::with_metric(start, Metric::Manhattan)
+    }
+
+    pub fn with_metric(start: IVec2, metric: Metric) -> AStar {
+        AStar {
+            start,
+            goal: None,
+            came_from: HashMap::new(),
+            cost_so_far: HashMap::from([(start, 0)]),
+            frontier: BinaryHeap::from([PositionNode {
+                position: start,
+                cost: 0,
+            }]),
+            expanded: HashSet::new(),
+            found: None,
+            metric,
+            position: start,
+            plan: None,
+        }
+    }
+
+    // Position the planner believes the agent is at
+    pub fn position(&self) -> IVec2 {
+        self.position
+    }
+
+    // Every position the search has reached along with the cheapest known cost from the start
+    // This includes frontier positions that have been reached but not yet expanded
+    pub fn explored(&self) -> &HashMap<IVec2, i32> {
+        &self.cost_so_far
+    }
+
+    // Positions waiting on the frontier, cheapest estimated total cost first
+    pub fn frontier_snapshot(&self) -> Vec<IVec2> {
+        let mut snapshot = Vec::new();
+        // The heap ordering is reversed, so its sorted order runs from the last to be expanded to the first
+        for node in self.frontier.clone().into_sorted_vec().into_iter().rev() {
+            if !self.expanded.contains(&node.position) && !snapshot.contains(&node.position) {
+                snapshot.push(node.position);
+            }
+        }
+        snapshot
+    }
+
+    // Throws away the search and any plan, starting over from the current position toward a new goal
+    fn restart(&mut self, goal: IVec2) {
+        *self = AStar::with_metric(self.position, self.metric);
+        self.goal = Some(goal);
+    }
+
+    // Advances the search by a single expansion, over the map itself or any view of it
+    pub fn step<G: Graph>(&mut self, graph: &G, goal: IVec2) -> SearchStep {
+        if self.goal != Some(goal) {
+            self.restart(goal);
+        }
+        if self.found == Some(goal) {
+            return SearchStep::Found;
+        }
+        loop {
+            let Some(current) = self.frontier.pop() else {
+                return SearchStep::Exhausted;
+            };
+            if current.position == goal {
+                self.found = Some(goal);
+                return SearchStep::Found;
+            }
+            // Positions can be queued several times, only the cheapest copy is expanded
+            if !self.expanded.insert(current.position) {
+                continue;
+            }
+
+            for (neighbor, step_cost) in graph.neighbors(current.position) {
+                let cost = self.cost_so_far[&current.position] + step_cost;
+                if self
+                    .cost_so_far
+                    .get(&neighbor)
+                    .is_none_or(|&known| cost < known)
+                {
+                    self.cost_so_far.insert(neighbor, cost);
+                    self.frontier.push(PositionNode {
+                        position: neighbor,
+                        cost: cost + graph.heuristic(self.metric, neighbor, goal),
+                    });
+                    self.came_from.insert(neighbor, current.position);
+                }
+            }
+            return SearchStep::Expanded(current.position);
+        }
+    }
+
+    // Path from the start to the goal once step has returned Found
+    pub fn path(&self) -> Option<Vec<IVec2>> {
+        self.found
+            .map(|goal| reconstruct_path(&self.came_from, self.start, goal))
+    }
+
+    // Returns the next step toward the goal, distinguishing arriving from being stuck
+    pub fn run(&mut self, map: &Map, goal: &IVec2) -> Step {
+        self.run_on(map, map, goal)
+    }
+
+    // Like run, but plans over a view of the map, such as one knowing which doors the agent can open
+    // The map is still used to turn each position on the plan into a step
+    pub fn run_on<G: Graph>(&mut self, map: &Map, graph: &G, goal: &IVec2) -> Step {
+        if self.position == *goal {
+            return Step::Arrived;
+        }
+        if self.goal != Some(*goal) {
+            self.restart(*goal);
+        }
+        if self.plan.is_none() {
+            while let SearchStep::Expanded(_) = self.step(graph, *goal) {}
+            self.plan = Some(match self.path() {
+                Some(mut path) => {
+                    // Next position is kept last, current position is dropped
+                    path.reverse();
+                    path.pop();
+                    path
+                }
+                None => Vec::new(),
+            });
+        }
+
+        let Some(next) = self.plan.as_mut().and_then(|plan| plan.pop()) else {
+            return Step::Stuck;
+        };
+        // Portal jumps are not a single move in any direction
+        let step = match Direction::all()
+            .into_iter()
+            .find(|direction| map.step(self.position, *direction, map.wrap_mode()) == Some(next))
+        {
+            Some(direction) => Step::Move(direction),
+            None if map.get_portal(&self.position) == Some(next) => Step::Teleport,
+            None => return Step::Stuck,
+        };
+        self.position = next;
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{CornerCutting, Layer};
+
+    fn walled_map() -> Map {
+        Map::load_from_reader("CCDCC\nCWWWC\nCCTCC\nKWLWC\n".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn adjacency_matches_get_neighbors() {
+        let map = walled_map();
+        let adjacency = map.build_adjacency();
+        for (pos, _tile) in map.get_tile_iterator() {
+            let mut expected: Vec<(IVec2, i32)> = map
+                .get_neighbors(&pos)
+                .into_iter()
+                .filter_map(|(neighbor, (_direction, tile))| {
+                    tile.movement_cost().map(|cost| (neighbor, cost))
+                })
+                .collect();
+            let mut actual = adjacency.neighbors_of(pos).to_vec();
+            expected.sort_by_key(|(neighbor, _)| (neighbor.y, neighbor.x));
+            actual.sort_by_key(|(neighbor, _)| (neighbor.y, neighbor.x));
+            assert_eq!(actual, expected, "neighbors of {}", pos);
+        }
+        assert!(adjacency.neighbors_of(IVec2::new(-1, 0)).is_empty());
+        assert!(adjacency.neighbors_of(IVec2::new(5, 0)).is_empty());
+    }
+
+    #[test]
+    fn shortest_path_uses_portal_to_skip_corridor() {
+        // The only way around the wall is a long corridor, unless the portal through it is taken
+        let mut map = Map::load_from_reader("CCCCCCC\nWWWWWWC\nCCCCCCC\n".as_bytes()).unwrap();
+        let (start, goal) = (IVec2::new(0, 2), IVec2::new(0, 0));
+        assert_eq!(
+            astar_with_cost(&map, start, goal).map(|(_, cost)| cost),
+            Some(14)
+        );
+
+        map.add_portal(IVec2::new(1, 2), IVec2::new(1, 0), false);
+        assert_eq!(
+            astar_with_cost(&map, start, goal),
+            Some((vec![start, IVec2::new(1, 2), IVec2::new(1, 0), goal], 3))
+        );
+        // Portals only lead one way unless added as bidirectional
+        assert_eq!(
+            astar_with_cost(&map, goal, start).map(|(_, cost)| cost),
+            Some(14)
+        );
+    }
+
+    #[test]
+    fn portal_heuristic_stays_admissible() {
+        // Walking back one tile to a portal beats walking the five tiles straight to the goal
+        let mut map = Map::new(12, 1);
+        map.add_portal(IVec2::new(4, 0), IVec2::new(9, 0), false);
+        let (start, goal) = (IVec2::new(5, 0), IVec2::new(10, 0));
+        assert!(map.heuristic(Metric::Manhattan, start, goal) <= 3);
+        for metric in [
+            Metric::Manhattan,
+            Metric::Euclidean,
+            Metric::Chebyshev,
+            Metric::Zero,
+        ] {
+            assert_eq!(
+                astar_with_metric(&map, start, goal, None, metric),
+                SearchOutcome::Found(vec![start, IVec2::new(4, 0), IVec2::new(9, 0), goal])
+            );
+        }
+        assert_eq!(
+            astar_with_cost(&map.build_adjacency(), start, goal).map(|(_, cost)| cost),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn stepping_finds_the_one_shot_path() {
+        let map = walled_map();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 2));
+        let mut planner = AStar::new(start);
+        let mut expansions = 0;
+        while let SearchStep::Expanded(_) = planner.step(&map, goal) {
+            expansions += 1;
+        }
+        assert!(expansions > 0);
+        assert_eq!(planner.step(&map, goal), SearchStep::Found);
+        assert_eq!(
+            planner.path().map(SearchOutcome::Found),
+            Some(astar(&map, start, goal, None))
+        );
+    }
+
+    #[test]
+    fn stepping_toward_a_new_goal_starts_over() {
+        let map = walled_map();
+        let start = IVec2::new(0, 0);
+        let mut planner = AStar::new(start);
+        planner.step(&map, IVec2::new(4, 3));
+        planner.step(&map, IVec2::new(4, 3));
+
+        let goal = IVec2::new(4, 0);
+        assert_eq!(planner.step(&map, goal), SearchStep::Expanded(start));
+        while let SearchStep::Expanded(_) = planner.step(&map, goal) {}
+        assert_eq!(
+            planner.path().map(SearchOutcome::Found),
+            Some(astar(&map, start, goal, None))
+        );
+
+        // A planner partway along its route replans from where it stands
+        let mut planner = AStar::new(start);
+        assert_eq!(planner.run(&map, &goal), Step::Move(Direction::Right));
+        assert_eq!(planner.run(&map, &start), Step::Move(Direction::Left));
+        assert_eq!(planner.run(&map, &start), Step::Arrived);
+    }
+
+    #[test]
+    fn stepped_planner_teleports_through_portals() {
+        let mut map = Map::new(12, 1);
+        map.add_portal(IVec2::new(4, 0), IVec2::new(9, 0), false);
+        let goal = IVec2::new(10, 0);
+        let mut planner = AStar::new(IVec2::new(5, 0));
+        assert_eq!(planner.run(&map, &goal), Step::Move(Direction::Left));
+        assert_eq!(planner.run(&map, &goal), Step::Teleport);
+        assert_eq!(planner.position(), IVec2::new(9, 0));
+        assert_eq!(planner.run(&map, &goal), Step::Move(Direction::Right));
+        assert_eq!(planner.run(&map, &goal), Step::Arrived);
+    }
+
+    #[test]
+    fn adjacency_searches_like_the_map() {
+        let map = walled_map();
+        let adjacency = map.build_adjacency();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 2));
+        assert_eq!(
+            astar(&adjacency, start, goal, None),
+            astar(&map, start, goal, None)
+        );
+        assert_eq!(
+            astar_with_cost(&adjacency, start, goal).map(|(_, cost)| cost),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn planner_searches_with_its_metric() {
+        let map = Map::from_fn(6, 6, |_| Tile::CLEAN);
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(5, 5));
+        let mut guided = AStar::new(start);
+        let mut blind = AStar::with_metric(start, Metric::Zero);
+        assert_eq!(guided.run(&map, &goal), blind.run(&map, &goal));
+        assert!(guided.explored().len() < blind.explored().len());
+    }
+
+    #[test]
+    fn path_cache_serves_repeats_until_routing_changes() {
+        let base = Map::load_from_reader("CCCCCCC\nWWWWWWC\nCCCCCCC\n".as_bytes()).unwrap();
+        let (start, goal) = (IVec2::new(0, 2), IVec2::new(0, 0));
+        let mut cache = PathCache::new();
+        let path = find_path_cached(&mut cache, &base, start, goal);
+        assert_eq!(path.as_ref().map(Vec::len), Some(15));
+        assert_eq!(find_path_cached(&mut cache, &base, start, goal), path);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+
+        let edits: [fn(&mut Map); 5] = [
+            |map| map.add_portal(IVec2::new(1, 2), IVec2::new(1, 0), false),
+            |map| map.set_one_way(IVec2::new(6, 1), Direction::Down),
+            |map| map.set_wrap_mode(WrapMode::Wrap),
+            |map| map.set_corner_cutting(CornerCutting::Allow),
+            |map| map.set_layer_tile(Layer::Objects, IVec2::new(3, 0), Tile::IMPASSABLE),
+        ];
+        for edit in edits {
+            let mut map = base.clone();
+            edit(&mut map);
+            let misses = cache.misses();
+            let fresh = match astar(&map, start, goal, None) {
+                SearchOutcome::Found(path) => Some(path),
+                _ => None,
+            };
+            assert_eq!(find_path_cached(&mut cache, &map, start, goal), fresh);
+            assert_eq!(cache.misses(), misses + 1);
+        }
+    }
+
+    // Sum of the costs of every tile entered along a route
+    fn route_cost(map: &Map, route: &[IVec2]) -> i32 {
+        route
+            .iter()
+            .skip(1)
+            .map(|pos| map.get_tile(*pos).unwrap().movement_cost().unwrap())
+            .sum()
+    }
+
+    #[test]
+    fn routes_are_measured_by_cost() {
+        // The dirty waypoint is fewer moves away but costs more to reach
+        let map = Map::load_from_reader("CCCCDD\n".as_bytes()).unwrap();
+        let start = IVec2::new(3, 0);
+        let (clean, dirty) = (IVec2::new(0, 0), IVec2::new(5, 0));
+        assert_eq!(shortest_path_length(&map, start, clean), Some(3));
+        assert_eq!(shortest_path_length(&map, start, dirty), Some(4));
+
+        let route = route_through(&map, start, &[dirty, clean]).unwrap();
+        assert_eq!(route[1], IVec2::new(2, 0));
+        assert_eq!(route.last(), Some(&dirty));
+    }
+
+    #[test]
+    fn optimal_route_has_the_lowest_total_cost() {
+        let map = Map::load_from_reader("CDDDC\nCWCWC\nCCDCC\n".as_bytes()).unwrap();
+        let start = IVec2::new(2, 1);
+        let waypoints = [IVec2::new(0, 0), IVec2::new(4, 0), IVec2::new(4, 2)];
+        let route = optimal_route(&map, start, &waypoints).unwrap();
+
+        let orders = [
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 0, 2],
+            [1, 2, 0],
+            [2, 0, 1],
+            [2, 1, 0],
+        ];
+        let cheapest = orders
+            .iter()
+            .map(|order| {
+                let mut current = start;
+                order
+                    .iter()
+                    .map(|index| {
+                        let cost = shortest_path_length(&map, current, waypoints[*index]).unwrap();
+                        current = waypoints[*index];
+                        cost
+                    })
+                    .sum::<i32>()
+            })
+            .min()
+            .unwrap();
+        assert_eq!(route_cost(&map, &route), cheapest);
+    }
+
+    #[test]
+    fn tiny_budget_exhausts_on_a_large_map() {
+        let map = Map::new(200, 200);
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(199, 199));
+        assert_eq!(astar(&map, start, goal, Some(10)), SearchOutcome::Exhausted);
+        assert_eq!(
+            dijkstra(&map, start, goal, Some(10)),
+            SearchOutcome::Exhausted
+        );
+        assert!(matches!(
+            astar(&map, start, goal, None),
+            SearchOutcome::Found(_)
+        ));
+    }
+
+    #[test]
+    fn unreachable_goal_is_no_path_within_budget() {
+        let map = Map::load_from_reader("CWC\n".as_bytes()).unwrap();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 0));
+        assert_eq!(astar(&map, start, goal, Some(10)), SearchOutcome::NoPath);
+    }
+
+    // Waypoints on a line where hopping straight to the end costs more than stopping in the middle
+    struct Waypoints;
+
+    impl Graph for Waypoints {
+        fn neighbors(&self, node: IVec2) -> impl Iterator<Item = (IVec2, i32)> + '_ {
+            let edges = match (node.x, node.y) {
+                (0, 0) => vec![(IVec2::new(5, 0), 3), (IVec2::new(10, 0), 20)],
+                (5, 0) => vec![(IVec2::new(10, 0), 4)],
+                _ => Vec::new(),
+            };
+            edges.into_iter()
+        }
+    }
+
+    #[test]
+    fn dijkstra_searches_a_custom_graph() {
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(10, 0));
+        assert_eq!(
+            dijkstra(&Waypoints, start, goal, None),
+            SearchOutcome::Found(vec![start, IVec2::new(5, 0), goal])
+        );
+        assert_eq!(
+            dijkstra(&Waypoints, goal, start, None),
+            SearchOutcome::NoPath
+        );
+    }
+
+    #[test]
+    fn dynamic_obstacles_are_routed_around() {
+        let map = Map::new(3, 2);
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(2, 0));
+        let straight = vec![start, IVec2::new(1, 0), goal];
+        assert_eq!(
+            astar(&map, start, goal, None),
+            SearchOutcome::Found(straight)
+        );
+
+        let blocked = Blocked::from([IVec2::new(1, 0)]);
+        let SearchOutcome::Found(path) =
+            astar(&map.with_dynamic_obstacles(&blocked), start, goal, None)
+        else {
+            panic!("expected a path around the blocked tile");
+        };
+        assert_eq!(path.len(), 5);
+        assert!(!path.contains(&IVec2::new(1, 0)));
+        assert_eq!(map.get_tile(IVec2::new(1, 0)), Some(&Tile::CLEAN));
+    }
+
+    #[test]
+    fn search_stops_at_the_nearest_dirty_tile() {
+        let map = Map::load_from_reader("DCCCC\nCWWWC\nCCCCD\n".as_bytes()).unwrap();
+        let start = IVec2::new(4, 0);
+        let path = find_path_until(&map, start, |_, tile| *tile == Tile::DIRTY).unwrap();
+        assert_eq!(path, [start, IVec2::new(4, 1), IVec2::new(4, 2)]);
+
+        assert_eq!(
+            find_path_until(&map, start, |_, tile| *tile == Tile::TARGET),
+            None
+        );
+    }
+
+    #[test]
+    fn wide_agents_avoid_narrow_gaps() {
+        let map = Map::load_from_reader("CCCCCCC\nCCCCCCC\nWWCWWCC\nCCCCCCC\nCCCCCCC\n".as_bytes())
+            .unwrap();
+        let (start, goal, gap) = (IVec2::new(0, 0), IVec2::new(0, 3), IVec2::new(2, 2));
+        let route = |min_clearance| match astar(
+            &map.with_min_clearance(min_clearance),
+            start,
+            goal,
+            None,
+        ) {
+            SearchOutcome::Found(path) => path,
+            outcome => panic!("expected a path, got {:?}", outcome),
+        };
+
+        let narrow = route(1);
+        assert!(narrow.contains(&gap));
+        assert_eq!(narrow.len(), 8);
+        let wide = route(2);
+        assert!(!wide.contains(&gap));
+        assert!(wide.contains(&IVec2::new(5, 2)));
+    }
+
+    #[test]
+    fn route_through_visits_every_waypoint() {
+        let map = walled_map();
+        let start = IVec2::new(0, 0);
+        let waypoints = [IVec2::new(4, 0), IVec2::new(0, 3), IVec2::new(2, 2)];
+        let route = route_through(&map, start, &waypoints).unwrap();
+        assert_eq!(route.first(), Some(&start));
+        assert!(waypoints.iter().all(|waypoint| route.contains(waypoint)));
+        // Every step of the route is a single move onto a passable tile
+        for pair in route.windows(2) {
+            assert!(map.neighbors(pair[0]).any(|(pos, _)| pos == pair[1]));
+        }
+
+        let unreachable = [IVec2::new(4, 0), IVec2::new(1, 1)];
+        assert_eq!(route_through(&map, start, &unreachable), None);
+    }
+
+    // Every ordering of the indices 0..count
+    fn permutations(count: usize) -> Vec<Vec<usize>> {
+        if count == 0 {
+            return vec![Vec::new()];
+        }
+        let mut orders = Vec::new();
+        for order in permutations(count - 1) {
+            for slot in 0..count {
+                let mut longer = order.clone();
+                longer.insert(slot, count - 1);
+                orders.push(longer);
+            }
+        }
+        orders
+    }
+
+    #[test]
+    fn optimal_route_matches_brute_force() {
+        let map =
+            Map::load_from_reader("CCCCCC\nCWWDWC\nCCCCCC\nCWCWWC\nCCCCCC\n".as_bytes()).unwrap();
+        let start = IVec2::new(0, 0);
+        let waypoints = [
+            IVec2::new(5, 4),
+            IVec2::new(3, 1),
+            IVec2::new(0, 4),
+            IVec2::new(5, 0),
+        ];
+        let brute_force = permutations(waypoints.len())
+            .into_iter()
+            .map(|order| {
+                let mut current = start;
+                let mut total = 0;
+                for index in order {
+                    total += shortest_path_length(&map, current, waypoints[index]).unwrap();
+                    current = waypoints[index];
+                }
+                total
+            })
+            .min()
+            .unwrap();
+
+        let route = optimal_route(&map, start, &waypoints).unwrap();
+        assert_eq!(route.first(), Some(&start));
+        assert!(waypoints.iter().all(|waypoint| route.contains(waypoint)));
+        assert_eq!(route_cost(&map, &route), brute_force);
+    }
+
+    #[test]
+    fn planner_steps_arrives_or_gets_stuck() {
+        let map = Map::load_from_reader("CCWC\n".as_bytes()).unwrap();
+        let mut planner = AStar::new(IVec2::new(0, 0));
+        assert_eq!(
+            planner.run(&map, &IVec2::new(1, 0)),
+            Step::Move(Direction::Right)
+        );
+        assert_eq!(planner.run(&map, &IVec2::new(1, 0)), Step::Arrived);
+        assert_eq!(planner.run(&map, &IVec2::new(3, 0)), Step::Stuck);
+        assert_eq!(planner.position(), IVec2::new(1, 0));
+    }
+
+    // Number of times a path changes direction
+    fn turns(path: &[IVec2]) -> usize {
+        let steps: Vec<IVec2> = path.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        steps.windows(2).filter(|pair| pair[0] != pair[1]).count()
+    }
+
+    #[test]
+    fn turn_penalty_prefers_straighter_paths() {
+        let map = Map::load_from_reader("CCWW\nCCCW\nCWCC\nCWWC\nCCCC\n".as_bytes()).unwrap();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(3, 3));
+        let SearchOutcome::Found(shortest) = astar(&map, start, goal, None) else {
+            panic!("expected a path up the staircase");
+        };
+        assert_eq!(shortest.len(), 7);
+        assert!(turns(&shortest) >= 4);
+
+        let straight = AStarWithFacing::new(10)
+            .find_path(&map, start, None, goal)
+            .unwrap();
+        assert_eq!(straight.len(), 9);
+        assert_eq!(turns(&straight), 2);
+        assert_eq!(
+            AStarWithFacing::new(0)
+                .find_path(&map, start, None, goal)
+                .map(|path| path.len()),
+            Some(7)
+        );
+    }
+
+    #[test]
+    fn every_metric_finds_the_optimal_cost() {
+        let map = Map::load_from_reader("CCDCC\nCWWWC\nCCDCC\nCWCWC\n".as_bytes()).unwrap();
+        let start = IVec2::new(0, 0);
+        for (goal, _tile) in map
+            .get_tile_iterator()
+            .filter(|(_, tile)| tile.is_passable())
+        {
+            let SearchOutcome::Found(optimal) = dijkstra(&map, start, goal, None) else {
+                panic!("{} should be reachable", goal);
+            };
+            for metric in [
+                Metric::Manhattan,
+                Metric::Euclidean,
+                Metric::Chebyshev,
+                Metric::Zero,
+            ] {
+                let SearchOutcome::Found(path) = astar_with_metric(&map, start, goal, None, metric)
+                else {
+                    panic!("{:?} found no path to {}", metric, goal);
+                };
+                assert_eq!(route_cost(&map, &path), route_cost(&map, &optimal));
+                // Every metric stays at or below Manhattan, which never exceeds the moves needed
+                assert!(
+                    metric.distance(&start, &goal) <= Metric::Manhattan.distance(&start, &goal)
+                );
+                assert!(Metric::Manhattan.distance(&start, &goal) < optimal.len() as i32);
+            }
+        }
+        assert_eq!(Metric::Euclidean.distance(&start, &IVec2::new(3, 4)), 5);
+        assert_eq!(Metric::Chebyshev.distance(&start, &IVec2::new(3, 4)), 4);
+    }
+
+    #[test]
+    fn cooperative_paths_pass_without_conflicts() {
+        // Two corridors cross, and both agents would reach the crossing on the same turn
+        let map = Map::load_from_reader("WWCWW\nWWCWW\nCCCCC\nWWCWW\nWWCWW\n".as_bytes()).unwrap();
+        let starts = [IVec2::new(0, 2), IVec2::new(2, 0)];
+        let goals = [IVec2::new(4, 2), IVec2::new(2, 4)];
+        let paths = cooperative_astar(&map, &starts, &goals).unwrap();
+        assert_eq!(paths.len(), 2);
+        for (path, (start, goal)) in paths.iter().zip(starts.iter().zip(&goals)) {
+            assert_eq!(path.first(), Some(start));
+            assert_eq!(path.last(), Some(goal));
+        }
+
+        // Agents stay on their goal once they have finished their path
+        let at = |path: &Vec<IVec2>, time: usize| path[time.min(path.len() - 1)];
+        let longest = paths.iter().map(Vec::len).max().unwrap();
+        assert_eq!(paths[0].len(), 5);
+        assert!(
+            paths[1].windows(2).any(|pair| pair[0] == pair[1]),
+            "the second agent must wait for the first to cross"
+        );
+        for time in 0..longest {
+            let (a, b) = (&paths[0], &paths[1]);
+            assert_ne!(
+                at(a, time),
+                at(b, time),
+                "both agents at one cell at time {}",
+                time
+            );
+            if time > 0 {
+                let swapped = at(a, time) == at(b, time - 1) && at(b, time) == at(a, time - 1);
+                assert!(!swapped, "agents swapped places at time {}", time);
+            }
+        }
+    }
+
+    #[test]
+    fn explored_covers_the_start_but_never_walls() {
+        let map = walled_map();
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(4, 3));
+        let mut planner = AStar::new(start);
+        planner.step(&map, goal);
+        planner.step(&map, goal);
+        let frontier = planner.frontier_snapshot();
+        assert!(!frontier.is_empty());
+        assert!(!frontier.contains(&start));
+        assert!(frontier
+            .iter()
+            .all(|pos| planner.explored().contains_key(pos)));
+
+        while let SearchStep::Expanded(_) = planner.step(&map, goal) {}
+        assert_eq!(planner.explored().get(&start), Some(&0));
+        assert!(planner
+            .explored()
+            .keys()
+            .all(|pos| map.get_tile(*pos).is_some_and(|tile| tile.is_passable())));
+    }
+
+    #[test]
+    fn searching_from_the_goal_finds_a_single_position_path() {
+        let map = walled_map();
+        let goal = IVec2::new(2, 2);
+        assert_eq!(
+            astar(&map, goal, goal, None),
+            SearchOutcome::Found(vec![goal])
+        );
+        assert_eq!(
+            astar(&map, goal, goal, Some(0)),
+            SearchOutcome::Found(vec![goal])
+        );
+        assert_eq!(astar_with_cost(&map, goal, goal), Some((vec![goal], 0)));
+        assert_eq!(map.astar_to(goal, goal), Some(vec![goal]));
+    }
+
+    #[test]
+    fn turn_free_facing_search_matches_astar_costs() {
+        let mut wrapped = Map::load_from_reader("CWCCC\nCWCWC\nCDCWC\n".as_bytes()).unwrap();
+        wrapped.set_wrap_mode(WrapMode::Wrap);
+        let cases = [
+            (walled_map(), IVec2::new(0, 0), IVec2::new(4, 3)),
+            (walled_map(), IVec2::new(4, 2), IVec2::new(0, 2)),
+            (wrapped, IVec2::new(0, 0), IVec2::new(4, 2)),
+        ];
+        for (map, start, goal) in cases {
+            let SearchOutcome::Found(optimal) = astar(&map, start, goal, None) else {
+                panic!("{} should be reachable", goal);
+            };
+            let path = AStarWithFacing::new(0)
+                .find_path(&map, start, None, goal)
+                .unwrap();
+            assert_eq!(route_cost(&map, &path), route_cost(&map, &optimal));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "turn penalty must not be negative")]
+    fn negative_turn_penalty_is_rejected() {
+        AStarWithFacing::new(-2);
+    }
+}
@@ -0,0 +1,84 @@
+use rand::Rng;
+
+use crate::action::Direction;
+
+// Picks a direction with probability proportional to exp(-cost / temperature)
+// Low temperatures approach a greedy choice while high temperatures approach a uniform one
+// Panics if there are no options
+pub fn softmax_choice(
+    options: &[(Direction, f32)],
+    temperature: f32,
+    rng: &mut impl Rng,
+) -> Direction {
+    assert!(
+        !options.is_empty(),
+        "softmax_choice requires at least one option"
+    );
+
+    // Shift by the lowest cost so the exponentials cannot overflow
+    let min_cost = options
+        .iter()
+        .map(|(_, cost)| *cost)
+        .fold(f32::INFINITY, f32::min);
+    let weights: Vec<f32> = options
+        .iter()
+        .map(|(_, cost)| (-(cost - min_cost) / temperature.max(f32::EPSILON)).exp())
+        .collect();
+
+    let mut roll = rng.random::<f32>() * weights.iter().sum::<f32>();
+    for ((direction, _), weight) in options.iter().zip(&weights) {
+        if roll < *weight {
+            return *direction;
+        }
+        roll -= weight;
+    }
+
+    // Floating point error can leave a tiny remainder, fall back to the last option
+    options[options.len() - 1].0
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn draws_follow_the_softmax_distribution() {
+        let options = [
+            (Direction::Up, 0.0),
+            (Direction::Right, 1.0),
+            (Direction::Down, 2.0),
+        ];
+        let mut rng = StdRng::seed_from_u64(7);
+        let draws = 20_000;
+        let mut counts = [0; 3];
+        for _ in 0..draws {
+            let choice = softmax_choice(&options, 1.0, &mut rng);
+            let index = options
+                .iter()
+                .position(|(direction, _)| *direction == choice);
+            counts[index.unwrap()] += 1;
+        }
+
+        let weights: Vec<f32> = options.iter().map(|(_, cost)| (-cost).exp()).collect();
+        let total: f32 = weights.iter().sum();
+        for (count, weight) in counts.iter().zip(&weights) {
+            let observed = *count as f32 / draws as f32;
+            assert!((observed - weight / total).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn temperature_moves_between_greedy_and_uniform() {
+        let options = [(Direction::Left, 0.0), (Direction::Right, 5.0)];
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!((0..1000).all(|_| softmax_choice(&options, 0.01, &mut rng) == Direction::Left));
+
+        let draws = 10_000;
+        let lefts = (0..draws)
+            .filter(|_| softmax_choice(&options, 1000.0, &mut rng) == Direction::Left)
+            .count();
+        assert!((lefts as f32 / draws as f32 - 0.5).abs() < 0.03);
+    }
+}
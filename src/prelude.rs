@@ -0,0 +1,29 @@
+//! Commonly used types, so `use csc411::prelude::*;` covers most programs
+//!
+//! ```
+//! use csc411::prelude::*;
+//! use glam::IVec2;
+//!
+//! let map = Map::load_from_reader("CCW\nWCT\n".as_bytes()).unwrap();
+//! assert_eq!(
+//!     astar(&map, IVec2::new(0, 0), IVec2::new(2, 1), None),
+//!     SearchOutcome::Found(vec![IVec2::new(0, 0), IVec2::new(1, 0), IVec2::new(1, 1), IVec2::new(2, 1)])
+//! );
+//!
+//! let mut env = ScenarioBuilder::new()
+//!     .map(map)
+//!     .agent_at(IVec2::new(0, 0))
+//!     .build()
+//!     .unwrap();
+//! env.step_n(10);
+//! assert_eq!(env.get_state().0, EnvironmentState::END);
+//! assert_eq!(env.agent().position(), IVec2::new(2, 1));
+//! ```
+pub use crate::{
+    action::{Action, Direction},
+    agent::{Agent, AgentState},
+    environment::{Environment, EnvironmentState, Event, StepResult},
+    map::{Map, Tile},
+    pathfinding::{astar, dijkstra, AStar, Graph, SearchOutcome},
+    simulation::{ScenarioBuilder, SimulationEnvironment},
+};
@@ -0,0 +1,436 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fmt::Display,
+};
+
+use glam::IVec2;
+use log::debug;
+
+use crate::{
+    action::Action,
+    agent::Agent,
+    agents::AStarAgent,
+    environment::{
+        CollisionPolicy, Environment, EnvironmentConfig, EnvironmentState, Event, StepResult,
+    },
+    map::{GridMap, Map},
+    pathfinding::Graph,
+    simulation::apply_action,
+};
+
+/**
+ * Two agent chase on the grid.
+ * Each turn the pursuer acts as an AStarAgent heading for the evader, then the evader picks the tile furthest from
+ * the pursuer among those it can reach first and takes one step of its own A* plan toward it. The environment ends
+ * once they share a tile. Both move at the same speed, so an evader on a map with a loop can circle it forever,
+ * which includes every open area at least two tiles wide; capture is only certain on maps without loops.
+ * A pursuer given its own goal no longer chases, so trying to move onto the evader's tile is a collision.
+ */
+pub struct PursuitEnvironment {
+    map: Map,
+    pursuer: AStarAgent,
+    // Plans toward the destination the environment picks each turn rather than toward a goal of its own
+    evader: AStarAgent,
+    config: EnvironmentConfig,
+
+    state: EnvironmentState,
+    turn_count: u32,
+    paused: bool,
+    events: Vec<Event>,
+    // Whether the pursuer has been told it reached its goal, so on_goal_reached fires once per arrival
+    notified: bool,
+    // Pursuer moves rejected by the map or by the evader standing in the way
+    collisions: u32,
+    // Action the pursuer took on the last step
+    last_action: Option<Action>,
+}
+
+impl PursuitEnvironment {
+    pub fn new(map: Map, pursuer_position: IVec2, evader_position: IVec2) -> Self {
+        Self::with_config(
+            map,
+            pursuer_position,
+            evader_position,
+            EnvironmentConfig::default(),
+        )
+    }
+
+    pub fn with_config(
+        map: Map,
+        pursuer_position: IVec2,
+        evader_position: IVec2,
+        config: EnvironmentConfig,
+    ) -> Self {
+        Self {
+            map,
+            pursuer: AStarAgent::with_symbol(pursuer_position, "P").with_metric(config.heuristic),
+            evader: AStarAgent::with_symbol(evader_position, "E"),
+            config,
+            state: EnvironmentState::START,
+            turn_count: 0,
+            paused: false,
+            events: Vec::new(),
+            notified: false,
+            collisions: 0,
+            last_action: None,
+        }
+    }
+
+    pub fn pursuer(&self) -> &AStarAgent {
+        &self.pursuer
+    }
+
+    pub fn pursuer_mut(&mut self) -> &mut AStarAgent {
+        &mut self.pursuer
+    }
+
+    pub fn evader(&self) -> &AStarAgent {
+        &self.evader
+    }
+
+    fn caught(&self) -> bool {
+        self.pursuer.position() == self.evader.position()
+    }
+
+    // The pursuer's own goal if it has chosen one, otherwise the evader
+    fn pursuer_goal(&self) -> IVec2 {
+        self.pursuer.goal().unwrap_or(self.evader.position())
+    }
+
+    // Tile an action would take an agent standing at from onto, None for actions that stay put
+    fn target_of(&self, from: IVec2, action: Action) -> Option<IVec2> {
+        match action {
+            Action::Move { direction } => self.map.step(from, direction, self.map.wrap_mode()),
+            Action::Teleport => self.map.get_portal(&from),
+            Action::Clean | Action::Wait => None,
+        }
+    }
+
+    // Whether an action would take the pursuer onto the evader's tile without chasing it
+    fn contests_evader(&self, action: Action) -> bool {
+        self.pursuer.goal().is_some()
+            && self.target_of(self.pursuer.position(), action) == Some(self.evader.position())
+    }
+
+    // Tile the evader runs to, the one furthest from the pursuer among those the evader reaches first
+    // Ties go to the tile closest to the evader and then to the first in row-major order
+    // Its own tile always qualifies while it has not been caught, so with nowhere safer to go it stays put
+    fn evader_destination(&self) -> IVec2 {
+        let from_pursuer = costs_from(&self.map, self.pursuer.position());
+        costs_from(&self.map, self.evader.position())
+            .iter()
+            .filter_map(|(pos, &cost)| {
+                let pursuer_cost = from_pursuer.get(&pos).copied().unwrap_or(i32::MAX);
+                (cost < pursuer_cost).then_some((pos, cost, pursuer_cost))
+            })
+            .max_by_key(|(pos, cost, pursuer_cost)| {
+                (*pursuer_cost, Reverse(*cost), Reverse((pos.y, pos.x)))
+            })
+            .map_or(self.evader.position(), |(pos, _, _)| pos)
+    }
+
+    // Runs the logic of a single step
+    fn advance(&mut self) {
+        if self.paused || self.state.is_finished() {
+            return;
+        }
+        self.turn_count += 1;
+        self.last_action = None;
+
+        // A pursuer with no way to its goal waits for the evader to come within reach
+        let goal = self.pursuer_goal();
+        let action = self.pursuer.choose_action(&self.map, Some(goal));
+        self.last_action = Some(action);
+        let moved = if self.contests_evader(action) {
+            debug!(
+                "pursuer blocked by the evader at {}",
+                self.evader.position()
+            );
+            Some(false)
+        } else {
+            apply_action(&mut self.map, &mut self.pursuer, action, &mut self.events)
+        };
+        if moved == Some(false) {
+            self.collisions += 1;
+            if self.config.collision_policy == CollisionPolicy::Error {
+                self.state = EnvironmentState::FAILED;
+                return;
+            }
+        }
+        if !self.caught() {
+            // The evader plans its own way to where it runs to, but never steps onto the pursuer
+            let destination = self.evader_destination();
+            let action = self.evader.choose_action(&self.map, Some(destination));
+            if self.target_of(self.evader.position(), action) != Some(self.pursuer.position()) {
+                apply_action(&mut self.map, &mut self.evader, action, &mut self.events);
+            }
+        }
+        // Staying on the goal does not count as reaching it again
+        let at_goal = self.pursuer.position() == self.pursuer_goal();
+        if at_goal && !self.notified {
+            self.events.push(Event::GoalReached {
+                symbol: self.pursuer.get_symbol(),
+            });
+            self.pursuer.on_goal_reached(&self.map);
+        }
+        self.notified = at_goal;
+
+        let ended = match &self.config.termination {
+            Some(condition) => condition.is_met(self),
+            None => self.caught(),
+        };
+        self.state = if ended {
+            EnvironmentState::END
+        } else if self
+            .config
+            .max_turns
+            .is_some_and(|max_turns| self.turn_count >= max_turns)
+        {
+            EnvironmentState::FAILED
+        } else {
+            EnvironmentState::RUN
+        };
+    }
+}
+
+impl Environment for PursuitEnvironment {
+    fn run(&mut self) -> StepResult {
+        let first_event = self.events.len();
+        self.advance();
+        let (state, turn) = self.get_state();
+        StepResult {
+            state,
+            turn,
+            events: self.events.drain(first_event..).collect(),
+        }
+    }
+
+    fn get_map(&self) -> &Map {
+        &self.map
+    }
+
+    fn get_agents(&self) -> Vec<Box<&impl Agent>> {
+        vec![Box::new(&self.pursuer), Box::new(&self.evader)]
+    }
+
+    fn agents_mut(&mut self) -> Vec<&mut dyn Agent> {
+        vec![&mut self.pursuer, &mut self.evader]
+    }
+
+    // The pursuer is chasing the evader unless it has chosen its own goal, while the evader has no fixed goal
+    fn get_goal(&self, agent: &impl Agent) -> Option<IVec2> {
+        if agent.get_symbol() == self.pursuer.get_symbol() {
+            Some(self.pursuer_goal())
+        } else {
+            agent.goal()
+        }
+    }
+
+    fn get_state(&self) -> (EnvironmentState, u32) {
+        if self.paused {
+            return (EnvironmentState::PAUSED, self.turn_count);
+        }
+        (self.state, self.turn_count)
+    }
+
+    fn get_environment_info(&self) -> HashMap<String, String> {
+        HashMap::from([("collisions".to_string(), self.collisions.to_string())])
+    }
+
+    fn config(&self) -> &EnvironmentConfig {
+        &self.config
+    }
+
+    fn paused_mut(&mut self) -> &mut bool {
+        &mut self.paused
+    }
+
+    fn last_action(&self) -> Option<Action> {
+        self.last_action
+    }
+
+    fn drain_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+// Cheapest cost of reaching every reachable position from start, found with Dijkstra's algorithm
+fn costs_from(map: &Map, start: IVec2) -> GridMap<i32> {
+    let mut costs = GridMap::new();
+    let mut frontier = BinaryHeap::from([Reverse((0, start.y, start.x))]);
+    while let Some(Reverse((cost, y, x))) = frontier.pop() {
+        let current = IVec2::new(x, y);
+        if costs.contains_key(&current) {
+            continue;
+        }
+        costs.insert(current, cost);
+        for (neighbor, step_cost) in map.neighbors(current) {
+            if !costs.contains_key(&neighbor) {
+                frontier.push(Reverse((cost + step_cost, neighbor.y, neighbor.x)));
+            }
+        }
+    }
+    costs
+}
+
+impl Display for PursuitEnvironment {
+    // Displays both agents on top of the map
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        environment::TerminationCondition,
+        map::{Tile, WrapMode},
+    };
+
+    fn run_to_finish(env: &mut impl Environment) -> (EnvironmentState, u32) {
+        env.step_n(1000);
+        env.get_state()
+    }
+
+    #[test]
+    fn catching_the_evader_is_reported_once() {
+        let map = Map::load_from_reader("CCCC\n".as_bytes()).unwrap();
+        let config = EnvironmentConfig {
+            termination: Some(TerminationCondition::MaxTurns(6)),
+            ..Default::default()
+        };
+        let mut env =
+            PursuitEnvironment::with_config(map, IVec2::new(0, 0), IVec2::new(3, 0), config);
+        let mut goals_reached = 0;
+        for _ in 0..6 {
+            goals_reached += env
+                .run()
+                .events
+                .iter()
+                .filter(|event| matches!(event, Event::GoalReached { .. }))
+                .count();
+        }
+        assert_eq!(env.get_state(), (EnvironmentState::END, 6));
+        assert_eq!(env.pursuer().position(), env.evader().position());
+        assert_eq!(goals_reached, 1);
+    }
+
+    #[test]
+    fn cleaning_pursuer_cleans_before_giving_chase() {
+        let map = Map::load_from_reader("DCCC\n".as_bytes()).unwrap();
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(3, 0));
+        env.pursuer_mut().set_cleaning(true);
+        let result = env.run();
+        assert_eq!(env.pursuer().position(), IVec2::new(0, 0));
+        assert_eq!(env.get_map().get_tile(IVec2::new(0, 0)), Some(&Tile::CLEAN));
+        assert!(result.events.contains(&Event::TileChanged {
+            pos: IVec2::new(0, 0),
+            old: Tile::DIRTY,
+            new: Tile::CLEAN,
+        }));
+        assert_eq!(run_to_finish(&mut env).0, EnvironmentState::END);
+    }
+
+    // A pursuer heading past the evader in a corridor, with the evader cornered at the far end
+    fn corridor(policy: CollisionPolicy) -> PursuitEnvironment {
+        let map = Map::load_from_reader("CCCCC\n".as_bytes()).unwrap();
+        let config = EnvironmentConfig {
+            collision_policy: policy,
+            max_turns: Some(6),
+            ..Default::default()
+        };
+        let mut env =
+            PursuitEnvironment::with_config(map, IVec2::new(0, 0), IVec2::new(2, 0), config);
+        env.pursuer_mut().set_goal(Some(IVec2::new(4, 0)));
+        env
+    }
+
+    #[test]
+    fn blocked_pursuer_counts_collisions() {
+        let mut env = corridor(CollisionPolicy::Block);
+        env.step_n(3);
+        assert_eq!(env.pursuer().position(), IVec2::new(3, 0));
+        assert_eq!(env.evader().position(), IVec2::new(4, 0));
+        assert_eq!(env.get_environment_info()["collisions"], "0");
+
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::FAILED, 6));
+        assert_eq!(env.pursuer().position(), IVec2::new(3, 0));
+        assert_eq!(env.get_environment_info()["collisions"], "3");
+    }
+
+    #[test]
+    fn collision_fails_under_error_policy() {
+        let mut env = corridor(CollisionPolicy::Error);
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::FAILED, 4));
+        assert_eq!(env.pursuer().position(), IVec2::new(3, 0));
+        assert_eq!(env.get_environment_info()["collisions"], "1");
+    }
+
+    #[test]
+    fn agents_can_be_moved_through_the_trait() {
+        let map = Map::new(6, 1);
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(5, 0));
+        for agent in env.agents_mut() {
+            let shifted =
+                agent.position() + IVec2::new(if agent.position().x == 0 { 1 } else { -1 }, 0);
+            agent.set_position(shifted);
+        }
+        assert_eq!(env.pursuer().position(), IVec2::new(1, 0));
+        assert_eq!(env.evader().position(), IVec2::new(4, 0));
+        let symbols: Vec<String> = env
+            .agents_mut()
+            .iter()
+            .map(|agent| agent.get_symbol())
+            .collect();
+        assert_eq!(symbols, ["P", "E"]);
+    }
+
+    #[test]
+    fn pursuer_catches_the_evader_on_an_open_corridor() {
+        let map = Map::new(7, 1);
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(3, 0));
+        env.run();
+        // The evader runs for the far end rather than waiting where it is
+        assert_eq!(env.evader().position(), IVec2::new(4, 0));
+        let (state, turn) = run_to_finish(&mut env);
+        assert_eq!(state, EnvironmentState::END);
+        assert!(turn <= 7, "caught after {} turns", turn);
+        assert_eq!(env.pursuer().position(), IVec2::new(6, 0));
+    }
+
+    #[test]
+    fn pursuer_corners_the_evader_in_a_dead_end() {
+        // Without loops to circle around, the evader is eventually trapped at the end of a branch
+        let map = Map::load_from_reader("CCCCC\nWCWCW\nWCWCW\nWCWWW\n".as_bytes()).unwrap();
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(3, 2));
+        let (state, turn) = run_to_finish(&mut env);
+        assert_eq!(state, EnvironmentState::END);
+        assert!(turn <= 20, "caught after {} turns", turn);
+        assert_eq!(env.pursuer().position(), env.evader().position());
+    }
+
+    #[test]
+    fn evader_escapes_across_a_wrapped_seam() {
+        let mut map = Map::load_from_reader("CCCCCC\n".as_bytes()).unwrap();
+        let mut env = PursuitEnvironment::new(map.clone(), IVec2::new(2, 0), IVec2::new(0, 0));
+        env.run();
+        assert_eq!(env.evader().position(), IVec2::new(0, 0));
+
+        map.set_wrap_mode(WrapMode::Wrap);
+        let mut env = PursuitEnvironment::new(map, IVec2::new(2, 0), IVec2::new(0, 0));
+        env.run();
+        assert_eq!(env.pursuer().position(), IVec2::new(1, 0));
+        assert_eq!(env.evader().position(), IVec2::new(5, 0));
+    }
+
+    #[test]
+    fn evader_circles_an_open_area_forever() {
+        let map = Map::new(4, 4);
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(3, 3));
+        env.step_n(100);
+        assert_eq!(env.get_state(), (EnvironmentState::RUN, 100));
+        assert_ne!(env.pursuer().position(), env.evader().position());
+    }
+}
@@ -0,0 +1,234 @@
+use glam::IVec2;
+
+use crate::{
+    agent::Agent,
+    environment::Environment,
+    map::{Map, Tile},
+};
+
+/**
+ * Options controlling how a map is drawn as text, the defaults match the map file format.
+ */
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions {
+    // Character drawn for each tile
+    pub codec: fn(&Tile) -> char,
+    // Draws the x coordinate of each column above the map and the y coordinate of each row to its left
+    // Only the last digit of x coordinates is shown so columns stay one character wide
+    pub coordinates: bool,
+    // Draws a box of + - and | characters around the map
+    pub border: bool,
+    // Character drawn for clean tiles instead of the codec's
+    pub empty_glyph: Option<char>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            codec: tile_char,
+            coordinates: false,
+            border: false,
+            empty_glyph: None,
+        }
+    }
+}
+
+// Character used for a tile in map files
+pub fn tile_char(tile: &Tile) -> char {
+    match tile {
+        Tile::IMPASSABLE => 'W',
+        Tile::CLEAN => 'C',
+        Tile::DIRTY => 'D',
+        Tile::TARGET => 'T',
+        Tile::KEY => 'K',
+        Tile::DOOR => 'L',
+    }
+}
+
+// Renders the map with walls drawn as box-drawing characters joined to their neighboring walls
+// Other tiles render the same as the map's Display
+pub fn render_autotiled(map: &Map) -> String {
+    let is_wall = |pos: IVec2| map.get_tile(pos) == Some(&Tile::IMPASSABLE);
+    let plain = map.to_string();
+
+    let mut output = String::new();
+    for (y, line) in plain.lines().enumerate() {
+        for (x, c) in line.chars().enumerate() {
+            let pos = IVec2::new(x as i32, y as i32);
+            if !is_wall(pos) {
+                output.push(c);
+                continue;
+            }
+            let up = is_wall(pos + IVec2::new(0, -1));
+            let down = is_wall(pos + IVec2::new(0, 1));
+            let left = is_wall(pos + IVec2::new(-1, 0));
+            let right = is_wall(pos + IVec2::new(1, 0));
+            output.push(match (up, down, left, right) {
+                (true, true, true, true) => '┼',
+                (true, true, true, false) => '┤',
+                (true, true, false, true) => '├',
+                (false, true, true, true) => '┬',
+                (true, false, true, true) => '┴',
+                (false, true, false, true) => '┌',
+                (false, true, true, false) => '┐',
+                (true, false, false, true) => '└',
+                (true, false, true, false) => '┘',
+                (_, _, false, false) => '│',
+                (false, false, _, _) => '─',
+            });
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// Renders the environment's map with each agent drawn using its style, colored with ANSI escape codes
+// Earlier agents are drawn over later ones, matching Environment::render
+pub fn render_colored(env: &impl Environment) -> String {
+    let overlays = env
+        .get_agents()
+        .iter()
+        .map(|agent| {
+            let style = agent.style();
+            let glyph = match style.color {
+                Some([r, g, b]) => format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, style.symbol),
+                None => style.symbol.to_string(),
+            };
+            (agent.position(), glyph)
+        })
+        .collect();
+    draw_over_map(env.get_map(), overlays)
+}
+
+// Draws the map with each overlay replacing the cell at its position, earlier overlays are drawn over later ones
+pub(crate) fn draw_over_map(map: &Map, overlays: Vec<(IVec2, String)>) -> String {
+    draw_with_options(map, &RenderOptions::default(), overlays)
+}
+
+// Draws the map as configured by the options, with overlays drawn over cells as in draw_over_map
+pub(crate) fn draw_with_options(
+    map: &Map,
+    opts: &RenderOptions,
+    overlays: Vec<(IVec2, String)>,
+) -> String {
+    let mut cells: Vec<Vec<String>> = map
+        .get_line_iterator()
+        .map(|line| {
+            line.into_iter()
+                .map(|(_, tile)| match (tile, opts.empty_glyph) {
+                    (Tile::CLEAN, Some(glyph)) => glyph.to_string(),
+                    _ => (opts.codec)(tile).to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    for (pos, glyph) in overlays.into_iter().rev() {
+        if let Some(cell) = cells
+            .get_mut(pos.y as usize)
+            .and_then(|row| row.get_mut(pos.x as usize))
+        {
+            *cell = glyph;
+        }
+    }
+
+    // Row labels are right aligned to the widest y coordinate and followed by a space
+    let gutter = if opts.coordinates {
+        map.height().saturating_sub(1).to_string().len() + 1
+    } else {
+        0
+    };
+    let edge = format!("{}+{}+\n", " ".repeat(gutter), "-".repeat(map.width()));
+
+    let mut output = String::new();
+    if opts.coordinates {
+        output.push_str(&" ".repeat(gutter + opts.border as usize));
+        output.extend((0..map.width()).map(|x| char::from(b'0' + (x % 10) as u8)));
+        output.push('\n');
+    }
+    if opts.border {
+        output.push_str(&edge);
+    }
+    for (y, row) in cells.into_iter().enumerate() {
+        if opts.coordinates {
+            output.push_str(&format!("{:>width$} ", y, width = gutter - 1));
+        }
+        if opts.border {
+            output.push('|');
+        }
+        output.push_str(&row.concat());
+        if opts.border {
+            output.push('|');
+        }
+        output.push('\n');
+    }
+    if opts.border {
+        output.push_str(&edge);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pursuit::PursuitEnvironment;
+
+    #[test]
+    fn room_walls_join_at_the_corners() {
+        let room = Map::load_from_reader("WWWW\nWCTW\nWWWW\n".as_bytes()).unwrap();
+        assert_eq!(render_autotiled(&room), "┌──┐\n│CT│\n└──┘\n");
+
+        let split = Map::load_from_reader("WWWWW\nWCWDW\nWWWWW\n".as_bytes()).unwrap();
+        assert_eq!(render_autotiled(&split), "┌─┬─┐\n│C│D│\n└─┴─┘\n");
+    }
+
+    #[test]
+    fn agents_render_in_their_own_styles() {
+        let map = Map::load_from_reader("CCCC\n".as_bytes()).unwrap();
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(3, 0));
+        env.pursuer_mut().set_color(Some([255, 0, 0]));
+        assert_eq!(render_colored(&env), "\x1b[38;2;255;0;0mP\x1b[0mCCE\n");
+        assert_eq!(env.render(), "PCCE\n");
+    }
+
+    #[test]
+    fn each_render_option_changes_the_output() {
+        let map = Map::load_from_reader("CW\nDT\n".as_bytes()).unwrap();
+        let defaults = RenderOptions::default();
+        assert_eq!(map.render_with(&defaults), "CW\nDT\n");
+        assert_eq!(map.render_with(&defaults), map.to_string());
+
+        let coordinates = RenderOptions {
+            coordinates: true,
+            ..defaults
+        };
+        assert_eq!(map.render_with(&coordinates), "  01\n0 CW\n1 DT\n");
+
+        let border = RenderOptions {
+            border: true,
+            ..defaults
+        };
+        assert_eq!(map.render_with(&border), "+--+\n|CW|\n|DT|\n+--+\n");
+
+        let empty = RenderOptions {
+            empty_glyph: Some('.'),
+            ..defaults
+        };
+        assert_eq!(map.render_with(&empty), ".W\nDT\n");
+
+        let codec = RenderOptions {
+            codec: |tile| if tile.is_passable() { ' ' } else { '#' },
+            ..defaults
+        };
+        assert_eq!(map.render_with(&codec), " #\n  \n");
+    }
+
+    #[test]
+    fn colored_render_shows_the_same_facing_as_render() {
+        let map = Map::load_from_reader("CCCCC\n".as_bytes()).unwrap();
+        let mut env = PursuitEnvironment::new(map, IVec2::new(0, 0), IVec2::new(4, 0));
+        env.pursuer_mut().set_color(Some([0, 0, 255]));
+        env.run();
+        assert_eq!(env.render(), "C>CCE\n");
+        assert_eq!(render_colored(&env), "C\x1b[38;2;0;0;255m>\x1b[0mCCE\n");
+    }
+}
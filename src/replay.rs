@@ -0,0 +1,130 @@
+use crate::{
+    action::Action,
+    environment::{Environment, EnvironmentState, StepResult},
+};
+
+/**
+ * Single (state, action, reward, next_state) tuple, as used by reinforcement learning.
+ * States are whatever the recorder's observation function extracts from the environment.
+ */
+#[derive(Clone, PartialEq, Debug)]
+pub struct Transition<O> {
+    pub state: O,
+    pub action: Action,
+    pub reward: f32,
+    pub next_state: O,
+}
+
+/**
+ * Steps an environment and records a transition for every action reported by Environment::last_action.
+ * The observation function is called before and after each step to capture the states, and the reward function is
+ * called with the environment as it is after the action was applied.
+ */
+pub struct TransitionRecorder<E, O, F, R>
+where
+    E: Environment,
+    F: Fn(&E) -> O,
+    R: Fn(&E, &Action) -> f32,
+{
+    env: E,
+    observe: F,
+    reward: R,
+    transitions: Vec<Transition<O>>,
+}
+
+impl<E, O, F, R> TransitionRecorder<E, O, F, R>
+where
+    E: Environment,
+    F: Fn(&E) -> O,
+    R: Fn(&E, &Action) -> f32,
+{
+    pub fn new(env: E, observe: F, reward: R) -> Self {
+        TransitionRecorder {
+            env,
+            observe,
+            reward,
+            transitions: Vec::new(),
+        }
+    }
+
+    pub fn env(&self) -> &E {
+        &self.env
+    }
+
+    pub fn transitions(&self) -> &[Transition<O>] {
+        &self.transitions
+    }
+
+    // Gives up the recorder, returning the transitions collected so far
+    pub fn into_transitions(self) -> Vec<Transition<O>> {
+        self.transitions
+    }
+
+    // Runs a single step of the environment, recording a transition if the agent acted
+    pub fn step(&mut self) -> StepResult {
+        let state = (self.observe)(&self.env);
+        let result = self.env.run();
+        if let Some(action) = self.env.last_action() {
+            let reward = (self.reward)(&self.env, &action);
+            let next_state = (self.observe)(&self.env);
+            self.transitions.push(Transition {
+                state,
+                action,
+                reward,
+                next_state,
+            });
+        }
+        result
+    }
+
+    // Steps until the environment ends or fails, or max_steps steps have run
+    pub fn run_episode(&mut self, max_steps: u32) -> EnvironmentState {
+        for _ in 0..max_steps {
+            if self.env.get_state().0.is_finished() {
+                break;
+            }
+            self.step();
+        }
+        self.env.get_state().0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::IVec2;
+
+    use super::*;
+    use crate::{
+        action::Direction,
+        agents::AStarAgent,
+        map::{Map, Tile},
+        simulation::GenericEnvironment,
+    };
+
+    #[test]
+    fn vacuum_rewards_each_cleaned_tile() {
+        let map = Map::load_from_reader("CDDC\n".as_bytes()).unwrap();
+        let agent = AStarAgent::new(IVec2::new(0, 0)).with_cleaning(true);
+        let env = GenericEnvironment::new(map, agent, IVec2::new(3, 0));
+        let mut recorder = TransitionRecorder::new(
+            env,
+            |env: &GenericEnvironment<AStarAgent>| env.get_map().get_all_of_type(Tile::DIRTY).len(),
+            |_, action| if *action == Action::Clean { 1.0 } else { 0.0 },
+        );
+        assert_eq!(recorder.run_episode(100), EnvironmentState::END);
+
+        let right = Action::Move {
+            direction: Direction::Right,
+        };
+        let transitions = recorder.into_transitions();
+        let actions: Vec<Action> = transitions.iter().map(|t| t.action).collect();
+        assert_eq!(actions, [right, Action::Clean, right, Action::Clean, right]);
+        let rewards: Vec<f32> = transitions.iter().map(|t| t.reward).collect();
+        assert_eq!(rewards, [0.0, 1.0, 0.0, 1.0, 0.0]);
+        let dirt: Vec<(usize, usize)> = transitions
+            .iter()
+            .map(|t| (t.state, t.next_state))
+            .collect();
+        assert_eq!(dirt, [(2, 2), (2, 1), (1, 1), (1, 0), (0, 0)]);
+    }
+}
@@ -0,0 +1,155 @@
+use std::{fmt::Display, fs};
+
+use glam::IVec2;
+use serde::{Deserialize, Serialize};
+
+use crate::{environment::EnvironmentConfig, map::Map};
+
+/**
+ * Starting setup for a single agent in a scenario.
+ */
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct AgentSpec {
+    pub symbol: String,
+    pub start: IVec2,
+    pub goal: Option<IVec2>,
+}
+
+/**
+ * Reproducible setup combining a map, agents, and configuration, stored as JSON.
+ * The map is stored as rows of tile characters, so portals and one-way tiles are not kept.
+ */
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Scenario {
+    #[serde(with = "map_rows")]
+    pub map: Map,
+    pub agents: Vec<AgentSpec>,
+    pub config: EnvironmentConfig,
+}
+
+/**
+ * Errors that can occur while loading, saving, or building a scenario.
+ */
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    MissingMap,
+    MissingAgent,
+    // No goal was given and the map has no target to default to
+    MissingGoal,
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(error) => write!(f, "{}", error),
+            ScenarioError::Json(error) => write!(f, "{}", error),
+            ScenarioError::MissingMap => write!(f, "The scenario has no map."),
+            ScenarioError::MissingAgent => write!(f, "The scenario has no agent position."),
+            ScenarioError::MissingGoal => {
+                write!(f, "The scenario has no goal and its map has no target.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<std::io::Error> for ScenarioError {
+    fn from(error: std::io::Error) -> Self {
+        ScenarioError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ScenarioError {
+    fn from(error: serde_json::Error) -> Self {
+        ScenarioError::Json(error)
+    }
+}
+
+impl Scenario {
+    pub fn load(path: &str) -> Result<Self, ScenarioError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ScenarioError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Stores a map as the rows of its Display output
+mod map_rows {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::map::Map;
+
+    pub fn serialize<S: Serializer>(map: &Map, serializer: S) -> Result<S::Ok, S::Error> {
+        let map_string = map.to_string();
+        let rows: Vec<&str> = map_string.lines().collect();
+        rows.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Map, D::Error> {
+        let rows = Vec::<String>::deserialize(deserializer)?;
+        Map::load_from_reader(rows.join("\n").as_bytes()).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{environment::CollisionPolicy, pathfinding::Metric};
+
+    fn two_agent_scenario() -> Scenario {
+        Scenario {
+            map: Map::load_from_reader("CCDC\nWCCT\n".as_bytes()).unwrap(),
+            agents: vec![
+                AgentSpec {
+                    symbol: "A".to_string(),
+                    start: IVec2::new(0, 0),
+                    goal: Some(IVec2::new(3, 1)),
+                },
+                AgentSpec {
+                    symbol: "B".to_string(),
+                    start: IVec2::new(3, 0),
+                    goal: None,
+                },
+            ],
+            config: EnvironmentConfig {
+                max_turns: Some(50),
+                collision_policy: CollisionPolicy::Error,
+                seed: 9,
+                energy: Some(20),
+                heuristic: Metric::Chebyshev,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn scenarios_round_trip_through_json() {
+        let scenario = two_agent_scenario();
+        let json = serde_json::to_string(&scenario).unwrap();
+        assert!(json.contains("\"CCDC\""));
+        let restored: Scenario = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, scenario);
+        assert_eq!(restored.map.to_string(), scenario.map.to_string());
+    }
+
+    #[test]
+    fn scenarios_save_and_load_from_files() {
+        let scenario = two_agent_scenario();
+        let path = std::env::temp_dir().join("csc411_scenarios_save_and_load_from_files.json");
+        let path = path.to_str().unwrap();
+        scenario.save(path).unwrap();
+        let loaded = Scenario::load(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), scenario);
+        assert!(matches!(
+            Scenario::load("no/such/scenario.json"),
+            Err(ScenarioError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,815 @@
+use std::{collections::HashMap, fmt::Display};
+
+use glam::IVec2;
+use log::debug;
+
+use crate::{
+    action::{Action, Direction},
+    agent::{Agent, AgentState, KEY_ITEM},
+    agents::AStarAgent,
+    environment::{
+        CollisionPolicy, Environment, EnvironmentConfig, EnvironmentState, Event, StepResult,
+    },
+    map::{Layer, Map, Tile},
+    pathfinding::Metric,
+    scenario::ScenarioError,
+};
+
+// Energy an agent uses up for each move it makes
+pub const MOVE_ENERGY_COST: u32 = 1;
+// Energy an agent uses up for each tile it cleans
+pub const CLEAN_ENERGY_COST: u32 = 1;
+
+/**
+ * Single robot environment where the robot follows A* toward its goal.
+ * This is a GenericEnvironment driven by an AStarAgent, so it fails as soon as the goal cannot be reached.
+ */
+pub type SimulationEnvironment = GenericEnvironment<AStarAgent>;
+
+// Carries out an agent's action, returning whether a move or jump went through
+// Returns None for actions that do not try to move the agent
+pub(crate) fn apply_action(
+    map: &mut Map,
+    agent: &mut dyn Agent,
+    action: Action,
+    events: &mut Vec<Event>,
+) -> Option<bool> {
+    match action {
+        Action::Move { direction } => Some(apply_move(map, agent, direction, events)),
+        Action::Teleport => Some(apply_teleport(map, agent, events)),
+        Action::Clean => {
+            apply_clean(map, agent, events);
+            None
+        }
+        Action::Wait => None,
+    }
+}
+
+// Moves an agent one tile in a direction if the map lets it, recording the move and any key picked up
+// Moves off the edge of the map follow its wrap mode and one-way tiles can only be left their way
+// Returns false if the move was rejected
+// Each move uses up MOVE_ENERGY_COST of the agent's energy
+fn apply_move(
+    map: &mut Map,
+    agent: &mut dyn Agent,
+    direction: Direction,
+    events: &mut Vec<Event>,
+) -> bool {
+    let from = agent.position();
+    // Clamping at an edge leads back onto the same tile, which is a blocked move rather than a move in place
+    let Some(next) = map
+        .step(from, direction, map.wrap_mode())
+        .filter(|next| *next != from && map.can_leave(&from, direction))
+    else {
+        debug!("rejected move from {} toward {:?}", from, direction);
+        return false;
+    };
+    if !move_to(map, agent, next, events) {
+        return false;
+    }
+    agent.set_facing(direction);
+    true
+}
+
+// Moves an agent through the portal it is standing on to the portal's exit
+// Returns false if there is no portal or its exit cannot be entered, a jump uses up energy like a move
+fn apply_teleport(map: &mut Map, agent: &mut dyn Agent, events: &mut Vec<Event>) -> bool {
+    let from = agent.position();
+    let Some(exit) = map.get_portal(&from) else {
+        debug!("rejected teleport from {} without a portal", from);
+        return false;
+    };
+    move_to(map, agent, exit, events)
+}
+
+// Puts an agent on a tile if it can enter it, recording the move and picking up any key there
+fn move_to(map: &mut Map, agent: &mut dyn Agent, next: IVec2, events: &mut Vec<Event>) -> bool {
+    let from = agent.position();
+    if !map
+        .get_tile(next)
+        .is_some_and(|tile| tile.is_passable_for(agent.state().unwrap_or(&AgentState::new())))
+    {
+        debug!("rejected move from {} to {}", from, next);
+        return false;
+    }
+
+    events.push(Event::AgentMoved {
+        symbol: agent.get_symbol(),
+        from,
+        to: next,
+    });
+    agent.set_position(next);
+    let Some(state) = agent.state_mut() else {
+        return true;
+    };
+    state.consume_energy(MOVE_ENERGY_COST);
+    // Keys are picked up by walking over them
+    if map.get_tile(next) == Some(&Tile::KEY) {
+        state.add(KEY_ITEM, 1);
+        clear_top_tile(map, next, events);
+    }
+    true
+}
+
+// Cleans the dirty tile an agent is standing on, cleaning anything else does nothing
+// Each clean uses up CLEAN_ENERGY_COST of the agent's energy
+fn apply_clean(map: &mut Map, agent: &mut dyn Agent, events: &mut Vec<Event>) {
+    let pos = agent.position();
+    if map.get_tile(pos) != Some(&Tile::DIRTY) {
+        return;
+    }
+    clear_top_tile(map, pos, events);
+    if let Some(state) = agent.state_mut() {
+        state.consume_energy(CLEAN_ENERGY_COST);
+    }
+}
+
+// Cleans the visible tile at a position, recording the change
+// Tiles placed as objects are removed to reveal the terrain beneath, terrain tiles become clean
+fn clear_top_tile(map: &mut Map, pos: IVec2, events: &mut Vec<Event>) {
+    let Some(old) = map.get_tile(pos).copied() else {
+        return;
+    };
+    if map.get_layer_tile(Layer::Objects, pos).is_some() {
+        map.clear_layer_tile(Layer::Objects, pos);
+    } else {
+        map.set_tile(pos, Tile::CLEAN);
+    }
+    events.push(Event::TileChanged {
+        pos,
+        old,
+        new: *map.get_tile(pos).unwrap(),
+    });
+}
+
+/**
+ * Single agent environment where the agent chooses its own actions and the environment only validates and applies them.
+ * Any planning lives in the agent, so agents such as AStarAgent or RandomAgent can be swapped in.
+ */
+pub struct GenericEnvironment<A: Agent> {
+    map: Map,
+    agent: A,
+    goal_position: IVec2,
+    config: EnvironmentConfig,
+
+    state: EnvironmentState,
+    turn_count: u32,
+    paused: bool,
+    events: Vec<Event>,
+    // Moves rejected because the target tile was blocked or off the map
+    collisions: u32,
+    // Action the agent chose on the most recent step, None if it was not asked
+    last_action: Option<Action>,
+    // Goal the agent was last told it reached, so on_goal_reached fires once per arrival
+    notified_goal: Option<IVec2>,
+}
+
+impl<A: Agent> GenericEnvironment<A> {
+    pub fn new(map: Map, agent: A, goal_position: IVec2) -> Self {
+        Self::with_config(map, agent, goal_position, EnvironmentConfig::default())
+    }
+
+    pub fn with_config(
+        map: Map,
+        mut agent: A,
+        goal_position: IVec2,
+        config: EnvironmentConfig,
+    ) -> Self {
+        if let (Some(energy), Some(state)) = (config.energy, agent.state_mut()) {
+            state.set_energy(Some(energy));
+        }
+        Self {
+            map,
+            agent,
+            goal_position,
+            config,
+            state: EnvironmentState::START,
+            turn_count: 0,
+            paused: false,
+            events: Vec::new(),
+            collisions: 0,
+            last_action: None,
+            notified_goal: None,
+        }
+    }
+
+    pub fn agent(&self) -> &A {
+        &self.agent
+    }
+
+    // The agent's own goal if it has chosen one, otherwise the goal the environment was created with
+    fn current_goal(&self) -> IVec2 {
+        self.agent.goal().unwrap_or(self.goal_position)
+    }
+
+    fn set_state(&mut self, state: EnvironmentState) {
+        if self.state != state {
+            debug!(
+                "environment state {:?} -> {:?} on turn {}",
+                self.state, state, self.turn_count
+            );
+        }
+        self.state = state;
+    }
+
+    // Calls the agent's goal hook if it is standing on its goal and has not been told yet
+    // Staying on the goal does not call it again, leaving and coming back does
+    fn notify_arrival(&mut self) {
+        let goal = self.current_goal();
+        if self.agent.position() != goal {
+            self.notified_goal = None;
+        } else if self.notified_goal != Some(goal) {
+            self.notified_goal = Some(goal);
+            self.agent.on_goal_reached(&self.map);
+        }
+    }
+
+    // Runs the logic of a single step
+    fn advance(&mut self) {
+        if self.paused || self.state.is_finished() {
+            return;
+        }
+        self.turn_count += 1;
+        self.last_action = None;
+
+        // An agent starting on its goal gets the chance to pick another before the environment ends
+        self.notify_arrival();
+        // Standing on the goal only ends the environment when no other termination condition is set
+        if self.config.termination.is_none() && self.agent.position() == self.current_goal() {
+            self.set_state(EnvironmentState::END);
+            return;
+        }
+        // An exhausted agent can neither move nor clean
+        if self.agent.state().is_some_and(AgentState::is_exhausted) {
+            debug!("agent ran out of energy at {}", self.agent.position());
+            self.set_state(EnvironmentState::FAILED);
+            return;
+        }
+        let action = self
+            .agent
+            .choose_action(&self.map, Some(self.current_goal()));
+        self.last_action = Some(action);
+        if self.agent.is_stuck() {
+            debug!("agent at {} cannot reach its goal", self.agent.position());
+            self.set_state(EnvironmentState::FAILED);
+            return;
+        }
+        let moved = apply_action(&mut self.map, &mut self.agent, action, &mut self.events);
+        match moved {
+            Some(false) => {
+                self.collisions += 1;
+                if self.config.collision_policy == CollisionPolicy::Error {
+                    self.set_state(EnvironmentState::FAILED);
+                    return;
+                }
+            }
+            Some(true) if self.agent.position() == self.current_goal() => {
+                self.events.push(Event::GoalReached {
+                    symbol: self.agent.get_symbol(),
+                });
+                self.notify_arrival();
+            }
+            _ => {}
+        }
+
+        // Agents that never reach the goal only fail once max_turns is reached
+        // Agents that picked a new goal on reaching theirs keep the environment running
+        let ended = match &self.config.termination {
+            Some(condition) => condition.is_met(self),
+            None => self.agent.position() == self.current_goal(),
+        };
+        self.set_state(if ended {
+            EnvironmentState::END
+        } else if self.agent.state().is_some_and(AgentState::is_exhausted) {
+            debug!("agent ran out of energy at {}", self.agent.position());
+            EnvironmentState::FAILED
+        } else if self
+            .config
+            .max_turns
+            .is_some_and(|max_turns| self.turn_count >= max_turns)
+        {
+            EnvironmentState::FAILED
+        } else {
+            EnvironmentState::RUN
+        });
+    }
+}
+
+impl<A: Agent> Environment for GenericEnvironment<A> {
+    fn run(&mut self) -> StepResult {
+        let first_event = self.events.len();
+        self.advance();
+        let (state, turn) = self.get_state();
+        StepResult {
+            state,
+            turn,
+            events: self.events.drain(first_event..).collect(),
+        }
+    }
+
+    fn get_agents(&self) -> Vec<Box<&impl Agent>> {
+        vec![Box::new(&self.agent)]
+    }
+
+    fn agents_mut(&mut self) -> Vec<&mut dyn Agent> {
+        vec![&mut self.agent]
+    }
+
+    fn get_goal(&self, _agent: &impl Agent) -> Option<IVec2> {
+        Some(self.current_goal())
+    }
+
+    fn get_state(&self) -> (EnvironmentState, u32) {
+        if self.paused {
+            return (EnvironmentState::PAUSED, self.turn_count);
+        }
+        (self.state, self.turn_count)
+    }
+
+    fn get_environment_info(&self) -> HashMap<String, String> {
+        let mut info = HashMap::from([("collisions".to_string(), self.collisions.to_string())]);
+        if let Some(energy) = self.agent.state().and_then(AgentState::energy) {
+            info.insert("energy".to_string(), energy.to_string());
+        }
+        info
+    }
+
+    fn get_map(&self) -> &Map {
+        &self.map
+    }
+
+    fn paused_mut(&mut self) -> &mut bool {
+        &mut self.paused
+    }
+
+    fn config(&self) -> &EnvironmentConfig {
+        &self.config
+    }
+
+    fn last_action(&self) -> Option<Action> {
+        self.last_action
+    }
+
+    fn drain_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl<A: Agent> Display for GenericEnvironment<A> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+/**
+ * Fluent builder for a SimulationEnvironment.
+ * A map and an agent position are required, the goal defaults to the first target on the map.
+ */
+#[derive(Default)]
+pub struct ScenarioBuilder {
+    map: Option<Map>,
+    agent_position: Option<IVec2>,
+    goal_position: Option<IVec2>,
+    config: EnvironmentConfig,
+}
+
+impl ScenarioBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map(mut self, map: Map) -> Self {
+        self.map = Some(map);
+        self
+    }
+
+    pub fn agent_at(mut self, position: IVec2) -> Self {
+        self.agent_position = Some(position);
+        self
+    }
+
+    pub fn goal(mut self, position: IVec2) -> Self {
+        self.goal_position = Some(position);
+        self
+    }
+
+    pub fn collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.config.collision_policy = policy;
+        self
+    }
+
+    pub fn heuristic(mut self, metric: Metric) -> Self {
+        self.config.heuristic = metric;
+        self
+    }
+
+    pub fn energy(mut self, energy: u32) -> Self {
+        self.config.energy = Some(energy);
+        self
+    }
+
+    pub fn config(mut self, config: EnvironmentConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Fails if the map or agent position are missing, or no goal can be found
+    pub fn build(self) -> Result<SimulationEnvironment, ScenarioError> {
+        let map = self.map.ok_or(ScenarioError::MissingMap)?;
+        let agent_position = self.agent_position.ok_or(ScenarioError::MissingAgent)?;
+        let goal_position = match self.goal_position {
+            Some(goal_position) => goal_position,
+            None => *map.targets().first().ok_or(ScenarioError::MissingGoal)?,
+        };
+
+        Ok(SimulationEnvironment::with_config(
+            map,
+            AStarAgent::from_config(agent_position, &self.config),
+            goal_position,
+            self.config,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::{GreedyAgent, RandomAgent};
+
+    fn open_map() -> Map {
+        Map::load_from_reader("CCCCC\nCCCCC\nCCCCT\n".as_bytes()).unwrap()
+    }
+
+    // Runs an environment until it finishes, giving up after a generous number of steps
+    fn run_to_finish(env: &mut impl Environment) -> (EnvironmentState, u32) {
+        env.step_n(1000);
+        env.get_state()
+    }
+
+    #[test]
+    fn agents_can_be_swapped_on_the_same_map() {
+        let (start, goal) = (IVec2::new(0, 0), IVec2::new(4, 2));
+
+        let mut env = GenericEnvironment::new(open_map(), AStarAgent::new(start), goal);
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+        assert_eq!(env.agent().position(), goal);
+
+        let mut env = GenericEnvironment::new(open_map(), GreedyAgent::new(start), goal);
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+
+        let mut env = GenericEnvironment::new(open_map(), RandomAgent::new(start, 7), goal);
+        assert_eq!(run_to_finish(&mut env).0, EnvironmentState::END);
+        assert_eq!(env.agent().position(), goal);
+    }
+
+    #[test]
+    fn simulation_environment_follows_astar() {
+        let map = Map::load_from_reader("CCCC\nWWWC\nTCCC\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(0, 2));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 8));
+
+        // A goal behind walls fails the environment instead of waiting forever
+        let map = Map::load_from_reader("CWT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(2, 0));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::FAILED, 1));
+    }
+
+    // Agent that takes a fixed list of actions, then waits
+    struct ScriptedAgent {
+        position: IVec2,
+        state: AgentState,
+        actions: Vec<Action>,
+    }
+
+    impl ScriptedAgent {
+        fn new(position: IVec2, actions: &[Action]) -> Self {
+            ScriptedAgent {
+                position,
+                state: AgentState::new(),
+                actions: actions.iter().rev().copied().collect(),
+            }
+        }
+    }
+
+    impl Agent for ScriptedAgent {
+        fn get_symbol(&self) -> String {
+            "S".to_string()
+        }
+
+        fn position(&self) -> IVec2 {
+            self.position
+        }
+
+        fn set_position(&mut self, position: IVec2) {
+            self.position = position;
+        }
+
+        fn state(&self) -> Option<&AgentState> {
+            Some(&self.state)
+        }
+
+        fn state_mut(&mut self) -> Option<&mut AgentState> {
+            Some(&mut self.state)
+        }
+
+        fn choose_action(&mut self, _map: &Map, _goal: Option<IVec2>) -> Action {
+            self.actions.pop().unwrap_or(Action::Wait)
+        }
+    }
+
+    const LEFT: Action = Action::Move {
+        direction: Direction::Left,
+    };
+    const RIGHT: Action = Action::Move {
+        direction: Direction::Right,
+    };
+
+    #[test]
+    fn key_opens_door() {
+        let map = Map::load_from_reader("KCLT\n".as_bytes()).unwrap();
+        let mut env = GenericEnvironment::new(
+            map,
+            ScriptedAgent::new(IVec2::new(1, 0), &[RIGHT, LEFT, RIGHT, RIGHT, RIGHT]),
+            IVec2::new(3, 0),
+        );
+        // The door is impassable until the key has been picked up
+        env.run();
+        assert_eq!(env.agent().position(), IVec2::new(1, 0));
+        assert_eq!(env.get_environment_info()["collisions"], "1");
+        env.run();
+        assert!(env.agent().state.has(KEY_ITEM));
+        assert_eq!(env.get_map().get_tile(IVec2::new(0, 0)), Some(&Tile::CLEAN));
+        env.step_n(3);
+        assert_eq!(env.agent().position(), IVec2::new(3, 0));
+        assert_eq!(env.get_state(), (EnvironmentState::END, 5));
+    }
+
+    #[test]
+    fn planner_replans_through_door_once_it_has_the_key() {
+        // Without a key the way round is 8 moves, the key on that way opens a shortcut through the door
+        let map = Map::load_from_reader("CCCLT\nKWWWC\nCCCCC\n".as_bytes()).unwrap();
+        let mut env = SimulationEnvironment::new(
+            map.clone(),
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(4, 0),
+        );
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+
+        // With no key on the map the door blocks the only way, unless the agent starts with one
+        let mut map = map;
+        map.set_tile(IVec2::new(0, 1), Tile::CLEAN);
+        map.set_tile(IVec2::new(4, 1), Tile::IMPASSABLE);
+        let mut env = GenericEnvironment::new(
+            map.clone(),
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(4, 0),
+        );
+        assert_eq!(run_to_finish(&mut env).0, EnvironmentState::FAILED);
+
+        let mut agent = AStarAgent::new(IVec2::new(0, 0));
+        agent.state_mut().unwrap().add(KEY_ITEM, 1);
+        let mut env = GenericEnvironment::new(map, agent, IVec2::new(4, 0));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 4));
+    }
+
+    // Heads to each waypoint in turn using an AStarAgent, counting how often it is told it arrived
+    struct PatrolAgent {
+        inner: AStarAgent,
+        waypoints: Vec<IVec2>,
+        arrivals: u32,
+    }
+
+    impl Agent for PatrolAgent {
+        fn get_symbol(&self) -> String {
+            "P".to_string()
+        }
+
+        fn position(&self) -> IVec2 {
+            self.inner.position()
+        }
+
+        fn set_position(&mut self, position: IVec2) {
+            self.inner.set_position(position);
+        }
+
+        fn choose_action(&mut self, map: &Map, goal: Option<IVec2>) -> Action {
+            self.inner.choose_action(map, goal)
+        }
+
+        fn goal(&self) -> Option<IVec2> {
+            self.waypoints.first().copied()
+        }
+
+        // Moves on to the next waypoint, staying on the last one
+        fn on_goal_reached(&mut self, _map: &Map) {
+            self.arrivals += 1;
+            if self.waypoints.len() > 1 {
+                self.waypoints.remove(0);
+            }
+        }
+    }
+
+    #[test]
+    fn goal_hook_routes_agent_to_next_goal() {
+        let (a, b) = (IVec2::new(4, 0), IVec2::new(0, 2));
+        let agent = PatrolAgent {
+            inner: AStarAgent::new(IVec2::new(0, 0)),
+            waypoints: vec![a, b],
+            arrivals: 0,
+        };
+        // The environment's own goal is ignored in favor of the agent's
+        let mut env = GenericEnvironment::new(open_map(), agent, IVec2::new(4, 2));
+        env.step_n(4);
+        assert_eq!(env.agent().position(), a);
+        assert_eq!(env.agent().arrivals, 1);
+        assert_eq!(env.get_goal(env.agent()), Some(b));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 10));
+        assert_eq!(env.agent().position(), b);
+        assert_eq!(env.agent().arrivals, 2);
+    }
+
+    #[test]
+    fn goal_hook_fires_once_per_arrival() {
+        let agent = PatrolAgent {
+            inner: AStarAgent::new(IVec2::new(0, 0)),
+            waypoints: vec![IVec2::new(0, 0)],
+            arrivals: 0,
+        };
+        let config = EnvironmentConfig {
+            termination: Some(crate::environment::TerminationCondition::MaxTurns(5)),
+            ..Default::default()
+        };
+        let mut env = GenericEnvironment::with_config(open_map(), agent, IVec2::new(4, 2), config);
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 5));
+        assert_eq!(env.agent().arrivals, 1);
+    }
+
+    fn corridor() -> Map {
+        Map::load_from_reader("CCCCCC\n".as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn running_out_of_energy_fails_short_of_the_goal() {
+        let mut env = ScenarioBuilder::new()
+            .map(corridor())
+            .agent_at(IVec2::new(0, 0))
+            .goal(IVec2::new(5, 0))
+            .energy(3)
+            .build()
+            .unwrap();
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::FAILED, 3));
+        assert_eq!(env.agent().position(), IVec2::new(3, 0));
+        assert_eq!(env.get_environment_info()["energy"], "0");
+    }
+
+    #[test]
+    fn agent_without_energy_does_not_move() {
+        let config = EnvironmentConfig {
+            energy: Some(0),
+            ..Default::default()
+        };
+        let mut env = GenericEnvironment::with_config(
+            corridor(),
+            AStarAgent::new(IVec2::new(0, 0)),
+            IVec2::new(5, 0),
+            config,
+        );
+        let result = env.run();
+        assert_eq!(result.state, EnvironmentState::FAILED);
+        assert!(result.events.is_empty());
+        assert_eq!(env.agent().position(), IVec2::new(0, 0));
+    }
+
+    #[test]
+    fn cleaning_agent_cleans_dirt_on_its_way() {
+        let map = Map::load_from_reader("CDDC\n".as_bytes()).unwrap();
+        let agent = AStarAgent::new(IVec2::new(0, 0)).with_cleaning(true);
+        let mut env = GenericEnvironment::new(map, agent, IVec2::new(3, 0));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 5));
+        assert!(env.get_map().get_all_of_type(Tile::DIRTY).is_empty());
+    }
+
+    #[test]
+    fn scenario_heuristic_reaches_the_planner() {
+        let mut env = ScenarioBuilder::new()
+            .map(open_map())
+            .agent_at(IVec2::new(0, 0))
+            .heuristic(Metric::Zero)
+            .build()
+            .unwrap();
+        assert_eq!(env.config().heuristic, Metric::Zero);
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+    }
+
+    #[test]
+    fn scenario_runs_to_its_target() {
+        let mut env = ScenarioBuilder::new()
+            .map(open_map())
+            .agent_at(IVec2::new(0, 0))
+            .build()
+            .unwrap();
+        assert_eq!(env.get_goal(env.agent()), Some(IVec2::new(4, 2)));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+    }
+
+    #[test]
+    fn incomplete_scenarios_do_not_build() {
+        let result = ScenarioBuilder::new().agent_at(IVec2::new(0, 0)).build();
+        assert!(matches!(result, Err(ScenarioError::MissingMap)));
+        let result = ScenarioBuilder::new().map(open_map()).build();
+        assert!(matches!(result, Err(ScenarioError::MissingAgent)));
+        let result = ScenarioBuilder::new()
+            .map(corridor())
+            .agent_at(IVec2::new(0, 0))
+            .build();
+        assert!(matches!(result, Err(ScenarioError::MissingGoal)));
+    }
+
+    #[test]
+    fn one_way_tiles_reject_moves_against_them() {
+        let mut map = corridor();
+        map.set_one_way(IVec2::new(1, 0), Direction::Left);
+        let agent = ScriptedAgent::new(IVec2::new(0, 0), &[RIGHT, RIGHT, LEFT]);
+        let mut env = GenericEnvironment::new(map, agent, IVec2::new(5, 0));
+        env.step_n(2);
+        assert_eq!(env.agent().position(), IVec2::new(1, 0));
+        assert_eq!(env.get_environment_info()["collisions"], "1");
+        env.run();
+        assert_eq!(env.agent().position(), IVec2::new(0, 0));
+    }
+
+    // Keeps the debug lines logged by the simulation so tests can look for them
+    struct CapturingLogger {
+        lines: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target().starts_with("csc411::simulation")
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.lines.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        lines: std::sync::Mutex::new(Vec::new()),
+    };
+
+    #[test]
+    fn reaching_the_goal_logs_the_state_change() {
+        // Only the first logger installed in the process is kept, so an error here is harmless
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Debug);
+
+        let map = Map::load_from_reader("CCCCCCT\n".as_bytes()).unwrap();
+        let mut env =
+            SimulationEnvironment::new(map, AStarAgent::new(IVec2::new(0, 0)), IVec2::new(6, 0));
+        assert_eq!(run_to_finish(&mut env), (EnvironmentState::END, 6));
+        assert!(LOGGER
+            .lines
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|line| line == "environment state RUN -> END on turn 6"));
+    }
+
+    #[test]
+    fn short_run_emits_moves_cleaning_and_arrival() {
+        let map = Map::load_from_reader("CDT\n".as_bytes()).unwrap();
+        let agent = AStarAgent::new(IVec2::new(0, 0)).with_cleaning(true);
+        let mut env = GenericEnvironment::new(map, agent, IVec2::new(2, 0));
+        let mut events = Vec::new();
+        while !env.get_state().0.is_finished() {
+            events.extend(env.run().events);
+        }
+        let moved = |from: IVec2, to: IVec2| Event::AgentMoved {
+            symbol: "A".to_string(),
+            from,
+            to,
+        };
+        assert_eq!(
+            events,
+            [
+                moved(IVec2::new(0, 0), IVec2::new(1, 0)),
+                Event::TileChanged {
+                    pos: IVec2::new(1, 0),
+                    old: Tile::DIRTY,
+                    new: Tile::CLEAN
+                },
+                moved(IVec2::new(1, 0), IVec2::new(2, 0)),
+                Event::GoalReached {
+                    symbol: "A".to_string()
+                },
+            ]
+        );
+        assert!(env.drain_events().is_empty());
+    }
+}